@@ -1,10 +1,10 @@
-use atomsh::{CWD, REPORT, PROMPT, INCOMPLETE_PROMPT, Error, Environment, Value, parse, PRELUDE_FILENAME, HISTORY_FILENAME};
+use atomsh::{CWD, ARGS, SCRIPT, run_exit_hooks, LAST_STATUS, REPORT, PROMPT, INCOMPLETE_PROMPT, Error, Environment, Value, parse, finished_jobs, interrupt, clear_interrupt, PRELUDE_FILENAME, CONFIG_DIRECTORY, HISTORY_FILENAME};
 use rustyline::{
     error::ReadlineError,
     Editor, Helper, Modifiers, KeyEvent, Cmd
 };
 
-use std::{borrow::Cow::{self, Borrowed, Owned}, env::current_dir, fs::read_to_string, sync::{Arc, Mutex}};
+use std::{borrow::Cow::{self, Borrowed, Owned}, env::{args, current_dir}, fs::{read_dir, read_to_string}, io::{stdin, IsTerminal, Read}, path::{Path, PathBuf}, process::exit};
 
 use rustyline::completion::{Completer, FilenameCompleter, Pair};
 use rustyline::config::OutputStreamType;
@@ -124,17 +124,18 @@ impl Validator for AtomHelper {
 }
 
 
-fn readline(prompt: impl ToString, rl: &mut Editor<impl Helper>) -> String {
+/// Read a line of input, or `None` at the end of the input.
+fn readline(prompt: impl ToString, rl: &mut Editor<impl Helper>) -> Option<String> {
     loop {
         match rl.readline(&prompt.to_string()) {
             Ok(line) => {
-                return line
+                return Some(line)
             },
             Err(ReadlineError::Interrupted) => {
-                return String::new();
+                return Some(String::new());
             },
             Err(ReadlineError::Eof) => {
-                return String::new();
+                return None;
             },
             Err(err) => {
                 eprintln!("error: {:?}", err);
@@ -143,37 +144,51 @@ fn readline(prompt: impl ToString, rl: &mut Editor<impl Helper>) -> String {
     }
 }
 
-fn repl(atomic_rl: Arc<Mutex<Editor<AtomHelper>>>, atomic_env: Arc<Mutex<Environment>>) -> Result<(), Error> {
+fn report(result: Result<Value, Error>, env: &mut Environment) -> Result<(), Error> {
+    // An interrupted evaluation has already stopped, so its error can still be reported
+    clear_interrupt();
+    let _ = Value::Apply(Box::new(env.get(REPORT)?), vec![match result {
+        Ok(val) => val,
+        Err(e)  => Value::Error(Box::new(e))
+    }]).eval(env);
+    Ok(())
+}
+
+fn repl(rl: &mut Editor<AtomHelper>, env: &mut Environment) -> Result<(), Error> {
     loop {
-        let mut env = atomic_env.lock().unwrap();
-        let mut rl = atomic_rl.lock().unwrap();
+        // A CTRL+C pressed after the last evaluation finished has nothing left to interrupt
+        clear_interrupt();
+        for job in finished_jobs() {
+            println!("{}", job);
+        }
 
-        let prompt = format!("{}", Value::Apply(Box::new(env.get(PROMPT)?), vec![Value::Path(env.get_cwd()?)]).eval(&mut env)?);
+        let prompt = format!("{}", Value::Apply(Box::new(env.get(PROMPT)?), vec![Value::Path(env.get_cwd()?)]).eval(env)?);
 
         rl.helper_mut().expect("No helper").set_prompt(format!("{}", prompt));
-        rl.helper_mut().expect("No helper").update_env(&env);
-        let mut text = readline(prompt, &mut rl);
+        rl.helper_mut().expect("No helper").update_env(env);
+        let mut text = match readline(prompt, rl) {
+            Some(text) => text,
+            // CTRL+D ends the session like `exit` does
+            None => {
+                run_exit_hooks(env);
+                return Ok(())
+            }
+        };
 
         if let Ok(parsed) = parse(&text) {
-            let _ = Value::Apply(Box::new(env.get(REPORT)?), vec![match parsed.eval(&mut env) {
-                Ok(val) => val,
-                Err(e)  => Value::Error(Box::new(e))
-            }]).eval(&mut env);
+            report(parsed.eval_top_level(env), env)?;
             rl.add_history_entry(text.as_str());
         } else if text.trim() != "" {
             rl.bind_sequence(KeyEvent::new('\t', Modifiers::NONE), Cmd::Insert(1, String::from("    ")));
             loop {
-                let err_prompt = format!("{}", Value::Apply(Box::new(env.get(INCOMPLETE_PROMPT)?), vec![Value::Path(env.get_cwd()?)]).eval(&mut env)?);
+                let err_prompt = format!("{}", Value::Apply(Box::new(env.get(INCOMPLETE_PROMPT)?), vec![Value::Path(env.get_cwd()?)]).eval(env)?);
                 rl.helper_mut().expect("No helper").set_prompt(format!("{}", &err_prompt));
-                rl.helper_mut().expect("No helper").update_env(&env);
-                let tmp = readline(&err_prompt, &mut rl);
+                rl.helper_mut().expect("No helper").update_env(env);
+                let tmp = readline(&err_prompt, rl).unwrap_or_default();
 
                 match parse(&text) {
                     Ok(parsed) => {
-                        let _ = Value::Apply(Box::new(env.get(REPORT)?), vec![match parsed.eval(&mut env) {
-                            Ok(val) => val,
-                            Err(e)  => Value::Error(Box::new(e))
-                        }]).eval(&mut env);
+                        report(parsed.eval_top_level(env), env)?;
                         rl.add_history_entry(text.as_str());
                         break
                     }
@@ -181,7 +196,7 @@ fn repl(atomic_rl: Arc<Mutex<Editor<AtomHelper>>>, atomic_env: Arc<Mutex<Environ
                         if tmp.trim() == "" {
                             let _ = Value::Apply(Box::new(env.get(REPORT)?), vec![
                                 Value::Error(Box::new(e))
-                            ]).eval(&mut env);
+                            ]).eval(env);
                             break
                         } else { text += &tmp }
                     }
@@ -196,9 +211,232 @@ fn repl(atomic_rl: Arc<Mutex<Editor<AtomHelper>>>, atomic_env: Arc<Mutex<Environ
     }
 }
 
+/// Evaluate a file that sets up the user's environment, such as the prelude.
+fn load_config(path: &Path, env: &mut Environment) {
+    match read_to_string(path) {
+        Ok(contents) => match parse(contents) {
+            Ok(parsed) => match parsed.eval(env) {
+                Ok(_) => {}
+                Err(e) => eprintln!("error in {}: {}", path.display(), e)
+            }
+            Err(e) => eprintln!("invalid syntax in {}\n{}", path.display(), e)
+        }
+        Err(_) => eprintln!("could not read {}", path.display())
+    }
+}
+
+/// Evaluate the prelude, followed by every `.atom` file in the config directory in order,
+/// so that the files in the config directory can extend or replace what the prelude defines.
+/// A custom prelude replaces the one in the home directory, which doesn't need to exist.
+fn load_prelude(prelude: Option<PathBuf>, with_prelude: bool, with_config: bool, env: &mut Environment) {
+    let home_dir = match env.get_home_dir() {
+        Ok(home_dir) => home_dir,
+        Err(_) => {
+            eprintln!("could not find the home directory to load the prelude from");
+            return
+        }
+    };
+
+    if with_prelude {
+        match prelude {
+            Some(path) => load_config(&path, env),
+            None => {
+                let path = home_dir.join(PRELUDE_FILENAME);
+                if path.exists() {
+                    load_config(&path, env);
+                }
+            }
+        }
+    }
+
+    if let (true, Ok(entries)) = (with_config, read_dir(home_dir.join(CONFIG_DIRECTORY))) {
+        let mut paths = entries.filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == "atom"))
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+        for path in paths {
+            load_config(&path, env);
+        }
+    }
+}
+
+fn set_cwd(env: &mut Environment) -> Result<(), Error> {
+    if let Ok(path) = current_dir() {
+        Value::Define(
+            String::from(CWD),
+            Box::new(Value::Path(path))
+        ).eval(env)?;
+    }
+    Ok(())
+}
+
+/// Run a script with the given arguments, and return the code the shell should exit with.
+/// The script's result is only printed with `report` when it's asked for.
+fn run_script(name: &str, contents: String, args: &[String], show_result: bool, env: &mut Environment) -> i32 {
+    env.define(ARGS, Value::List(args.iter().map(Value::string).collect()));
+
+    match parse(contents) {
+        Ok(parsed) => {
+            let result = parsed.eval_top_level(env);
+            let status = match env.get(LAST_STATUS) {
+                Ok(Value::Integer(code)) => code,
+                _ => 0
+            };
+            // A script that stopped with an error always fails, like a shell script run with `set -e`
+            let code = match &result {
+                Err(Error::Interrupted) => 130,
+                Err(_) => status.max(1),
+                Ok(_) => status
+            };
+
+            match result {
+                result if show_result => { let _ = report(result, env); }
+                Err(e) => eprintln!("error: {}", e),
+                Ok(_) => {}
+            }
+            // The exit hooks still run after an interrupt
+            clear_interrupt();
+            run_exit_hooks(env);
+            code
+        }
+        Err(e) => {
+            eprintln!("invalid syntax in {}\n{}", name, e);
+            2
+        }
+    }
+}
+
+const USAGE: &str = "usage: atom [--report] [--no-prelude | --prelude file] [--no-config] [-c code | script] [args...]
+       atom (--check | --ast) [-c code | files...]";
+
 fn main() -> Result<(), Error> {
     let mut env = Environment::new();
 
+    // Scripts are interrupted like the REPL is, instead of being killed, so that their exit hooks still run
+    if ctrlc::set_handler(interrupt).is_err() {
+        eprintln!("could not establish CTRL+C handler")
+    }
+
+    let mut args = args().skip(1);
+    let mut show_result = false;
+    let mut load = true;
+    let mut load_config_dir = true;
+    let mut prelude = None;
+    let mut code = None;
+    let mut script = None;
+    let mut check = false;
+    let mut ast = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" | "--report" => show_result = true,
+            "--no-prelude" => load = false,
+            "--no-config" => load_config_dir = false,
+            "--check" => check = true,
+            "--ast" => ast = true,
+            "--prelude" => match args.next() {
+                Some(path) => prelude = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("{}", USAGE);
+                    exit(2)
+                }
+            },
+            "-c" => match args.next() {
+                Some(text) => {
+                    code = Some(text);
+                    break
+                }
+                None => {
+                    eprintln!("{}", USAGE);
+                    exit(2)
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0)
+            }
+            flag if flag.starts_with('-') => {
+                eprintln!("unknown option {}\n{}", flag, USAGE);
+                exit(2)
+            }
+            _ => {
+                script = Some(arg);
+                break
+            }
+        }
+    }
+    let args = args.collect::<Vec<String>>();
+
+    // Parse without running anything, for editors and commit hooks
+    if check || ast {
+        let sources = match (code, script) {
+            (Some(code), _) => vec![(String::from("-c"), Some(code))],
+            (None, Some(path)) => std::iter::once(path).chain(args)
+                .map(|path| {
+                    let contents = read_to_string(&path).ok();
+                    (path, contents)
+                })
+                .collect(),
+            (None, None) => {
+                let mut contents = String::new();
+                let contents = stdin().read_to_string(&mut contents).ok().map(|_| contents);
+                vec![(String::from("standard input"), contents)]
+            }
+        };
+
+        let mut code = 0;
+        for (name, contents) in sources {
+            match contents.map(parse) {
+                Some(Ok(parsed)) => if ast {
+                    print!("{}", parsed.ast())
+                }
+                Some(Err(e)) => {
+                    eprintln!("invalid syntax in {}\n{}", name, e);
+                    code = 2
+                }
+                None => {
+                    eprintln!("could not read {}", name);
+                    code = code.max(1)
+                }
+            }
+        }
+        exit(code)
+    }
+
+    // Code given on the command line, in a script file, or piped to atom is run without starting a session.
+    // Scripts only load a prelude that's asked for, so that they behave the same for everyone
+    let source = match (code, script) {
+        (Some(code), _) => Some((String::from("-c"), code)),
+        (None, Some(path)) => match read_to_string(&path) {
+            Ok(contents) => {
+                // Modules are imported from the script's directory
+                env.define(SCRIPT, Value::Path(dunce::canonicalize(&path).unwrap_or_else(|_| PathBuf::from(&path))));
+                Some((path, contents))
+            }
+            Err(_) => {
+                eprintln!("could not read {}", path);
+                exit(1)
+            }
+        },
+        (None, None) if !stdin().is_terminal() => {
+            let mut contents = String::new();
+            if stdin().read_to_string(&mut contents).is_err() {
+                eprintln!("could not read standard input");
+                exit(1)
+            }
+            Some((String::from("standard input"), contents))
+        }
+        (None, None) => None
+    };
+
+    if let Some((name, contents)) = source {
+        if let (true, Some(path)) = (load, prelude) {
+            load_config(&path, &mut env);
+        }
+        set_cwd(&mut env)?;
+        exit(run_script(&name, contents, &args, show_result, &mut env));
+    }
+
     let config = Config::builder()
         .history_ignore_dups(true)
         .history_ignore_space(true)
@@ -225,41 +463,9 @@ fn main() -> Result<(), Error> {
         println!("No previous history.");
     }
 
-    if let Ok(home_dir) = env.get_home_dir() {
-        if let Ok(contents) = read_to_string(home_dir.join(PRELUDE_FILENAME)) {
-            match parse(contents) {
-                Ok(parsed) => match parsed.eval(&mut env) {
-                    Ok(_) => {}
-                    Err(e) => eprintln!("error in {}: {}", PRELUDE_FILENAME, e)
-                }
-                Err(e) => eprintln!("invalid syntax in {}\n{}", PRELUDE_FILENAME, e)
-            }
-        } else {
-            eprintln!("could not read {}", PRELUDE_FILENAME)
-        }
-    } else {
-        eprintln!("could not read {}", PRELUDE_FILENAME)
-    }
-
-    if let Ok(path) = current_dir() {
-        Value::Define(
-            String::from(CWD),
-            Box::new(Value::Path(path))
-        ).eval(&mut env)?;
-    }
-
-    let atomic_rl = Arc::new(Mutex::new(rl));
-    let atomic_env = Arc::new(Mutex::new(env));
+    load_prelude(prelude, load, load_config_dir, &mut env);
+    set_cwd(&mut env)?;
     
-    let atomic_rl_clone = atomic_rl.clone();
-    let atomic_env_clone = atomic_env.clone();
-    
-    if ctrlc::set_handler(move || {
-        let _ = repl(atomic_rl_clone.clone(), atomic_env_clone.clone());
-    }).is_err() {
-        eprintln!("could not establish CTRL+C handler")
-    }
-    
-    repl(atomic_rl, atomic_env)?;
+    repl(&mut rl, &mut env)?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,14 +1,17 @@
 use std::{
     thread::sleep,
-    time::Duration,
+    time::{Duration, Instant},
     collections::BTreeMap,
     process::exit,
+    sync::Mutex,
     path::{PathBuf, Component},
-    env::current_exe,
-    io::{stdin, stdout, Write},
-    fs::{read_to_string, write}
+    env::{current_exe, var, var_os, vars, set_var, remove_var, split_paths},
+    ffi::OsString,
+    io::{stdin, stdout, Write, ErrorKind},
+    fs::{read_to_string, write},
+    process::Stdio
 };
-use super::{Value, Error, VERSION, PRELUDE_FILENAME};
+use super::{Value, Param, Error, VERSION, PRELUDE_FILENAME, parse, process};
 
 use rand::{seq::SliceRandom, Rng, thread_rng, distributions::Uniform};
 use chrono::{Local, Timelike, Datelike};
@@ -946,6 +949,19 @@ fn get_os_family(t: &Type) -> String {
 pub const REPORT: &str = "report";
 pub const PROMPT: &str = "prompt";
 pub const INCOMPLETE_PROMPT: &str = "incomplete-prompt";
+pub const PIPEFAIL: &str = "PIPEFAIL";
+pub const COMMAND_NOT_FOUND: &str = "command-not-found";
+pub const TIMEOUT: &str = "TIMEOUT";
+pub const GLOB: &str = "GLOB";
+pub const STRICT: &str = "STRICT";
+pub const LAST_STATUS: &str = "LAST-STATUS";
+pub const LAST_DURATION: &str = "LAST-DURATION";
+pub const LAST_ERROR: &str = "LAST-ERROR";
+pub const ARGS: &str = "ARGS";
+/// The path of the script being run, which `import` searches for modules next to.
+pub const SCRIPT: &str = "SCRIPT";
+/// The table of environment variables that `with-env` adds to programs run in its body.
+const ENV_VARS: &str = "ENV-VARS";
 
 pub const CWD:   &str = "CWD";
 const HOME:      &str = "HOME";
@@ -962,6 +978,131 @@ fn check_args_len(func: Value, args: &Vec<Value>, len: usize) -> Result<(), Erro
     else { Ok(()) }
 }
 
+lazy_static! {
+    /// The functions registered with `on-exit`, in the order they were registered.
+    static ref EXIT_HOOKS: Mutex<Vec<Value>> = Mutex::new(vec![]);
+    /// The tables of definitions made by each imported file, keyed by its canonical path.
+    static ref MODULES: Mutex<BTreeMap<PathBuf, Value>> = Mutex::new(BTreeMap::new());
+    /// The files that are currently being imported, used to catch circular imports.
+    static ref IMPORTING: Mutex<Vec<PathBuf>> = Mutex::new(vec![]);
+}
+
+/// Run every function registered with `on-exit`, in the order they were registered.
+/// An error in one hook is reported, and doesn't stop the hooks after it from running.
+pub fn run_exit_hooks(env: &mut Environment) {
+    // The hooks are taken out first, so that a hook calling `exit` doesn't run them again
+    let hooks = std::mem::take(&mut *EXIT_HOOKS.lock().unwrap());
+    for hook in hooks {
+        match Value::Apply(Box::new(hook), vec![]).eval(env) {
+            Ok(Value::Error(e)) => eprintln!("error in exit hook: {}", e),
+            Err(e) => eprintln!("error in exit hook: {}", e),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Find the file for a module name, searching the running script's directory and then each directory in `ATOM_PATH`.
+fn find_module(name: &str, env: &Environment) -> Result<Option<PathBuf>, Error> {
+    let cwd = env.get_cwd()?;
+    let mut dirs = vec![match env.get(SCRIPT) {
+        Ok(Value::Path(script)) => script.parent().map(PathBuf::from).unwrap_or_else(|| cwd.clone()),
+        _ => cwd.clone()
+    }];
+
+    // Like `PATH`, respect an `ATOM_PATH` set by `with-env`
+    let atom_path = match env.get_env_vars().remove("ATOM_PATH") {
+        Some(path) => path.map(OsString::from),
+        None => var_os("ATOM_PATH")
+    };
+    if let Some(atom_path) = atom_path {
+        dirs.extend(split_paths(&atom_path).map(|dir| cwd.join(dir)));
+    }
+
+    let file = format!("{}.atom", name);
+    Ok(dirs.into_iter()
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .map(|path| dunce::canonicalize(&path).unwrap_or(path)))
+}
+
+/// Evaluate a file in a fresh environment, and return a table of everything it defines.
+fn import(path: PathBuf, env: &Environment) -> Result<Value, Error> {
+    if let Some(module) = MODULES.lock().unwrap().get(&path) {
+        return Ok(module.clone())
+    }
+    if IMPORTING.lock().unwrap().contains(&path) {
+        return Err(Error::CustomError(format!("circular import of {:?}", path)))
+    }
+
+    let contents = match read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => return Err(Error::CustomError(format!("could not read file {:?}", path)))
+    };
+
+    let mut module = Environment::new();
+    module.define(CWD, Value::Path(env.get_cwd()?));
+    module.define(SCRIPT, Value::Path(path.clone()));
+
+    // The lock isn't held while the file runs, because it may import other files
+    IMPORTING.lock().unwrap().push(path.clone());
+    let result = parse(contents).and_then(|program| program.eval(&mut module));
+    IMPORTING.lock().unwrap().retain(|importing| *importing != path);
+    result?;
+
+    let mut definitions = module.get_symbols().clone();
+    definitions.remove(CWD);
+    definitions.remove(SCRIPT);
+    let table = Value::Table(definitions);
+    MODULES.lock().unwrap().insert(path, table.clone());
+    Ok(table)
+}
+
+/// Evaluate the single job handle passed to a job control builtin.
+fn job_arg(func: Value, args: &[Value], env: &mut Environment) -> Result<usize, Error> {
+    check_args_len(func.clone(), &args.to_vec(), 1)?;
+    match args[0].eval(env)? {
+        Value::Job(id) => Ok(id),
+        Value::Integer(id) if id > 0 => Ok(id as usize),
+        _ => Err(Error::InvalidArguments(func, args.to_vec()))
+    }
+}
+
+type ProgramArgs = (Value, Vec<String>, BTreeMap<String, Value>);
+
+/// Evaluate the arguments to a builtin that runs a program, in the form
+/// `(program, [args], { options })`. The argument list and options table are optional,
+/// and only the option names in `allowed` are accepted.
+fn program_args(func: Value, args: &[Value], env: &mut Environment, allowed: &[&str]) -> Result<ProgramArgs, Error> {
+    if args.is_empty() {
+        return Err(Error::TooFewArguments(func, args.to_vec()))
+    } else if args.len() > 3 {
+        return Err(Error::TooManyArguments(func, args.to_vec()))
+    }
+
+    let program = match args[0].eval(env)? {
+        program @ Value::Path(_) | program @ Value::String(_) | program @ Value::Symbol(_) => program,
+        _ => return Err(Error::InvalidArguments(func, args.to_vec()))
+    };
+
+    let arguments = match args.get(1) {
+        Some(arg) => match arg.eval(env)? {
+            Value::List(list) => list.iter().map(ToString::to_string).collect(),
+            _ => return Err(Error::InvalidArguments(func, args.to_vec()))
+        },
+        None => vec![]
+    };
+
+    let options = match args.get(2) {
+        Some(arg) => match arg.eval(env)? {
+            Value::Table(options) if options.keys().all(|key| allowed.contains(&key.as_str())) => options,
+            _ => return Err(Error::InvalidArguments(func, args.to_vec()))
+        },
+        None => BTreeMap::new()
+    };
+
+    Ok((program, arguments, options))
+}
+
 impl Environment {
     pub fn new() -> Self {
         let mut result = Self { symbols: BTreeMap::new() };
@@ -1003,6 +1144,21 @@ impl Environment {
                     Ok(Value::String(format!("{}> ", " ".repeat(format!("{}", args[0].eval(env)?).len()))))
                 }),
 
+                PIPEFAIL => Value::Boolean(false),
+                TIMEOUT => Value::Nil,
+                GLOB => Value::Boolean(false),
+                STRICT => Value::Boolean(false),
+                LAST_STATUS => Value::Integer(0),
+                LAST_DURATION => Value::Float(0.0),
+                LAST_ERROR => Value::Nil,
+                ARGS => Value::List(vec![]),
+                SCRIPT => Value::Nil,
+
+                COMMAND_NOT_FOUND => Value::builtin(COMMAND_NOT_FOUND, |args, env| {
+                    check_args_len(env.get(COMMAND_NOT_FOUND)?, args, 2)?;
+                    Err(Error::CannotExecuteProgram(args[0].eval(env)?))
+                }),
+
                 "absolute" => Value::builtin("absolute", |args, env| {
                     check_args_len(env.get("absolute")?, &args, 1)?;
 
@@ -1032,6 +1188,195 @@ impl Environment {
                     }
                 }),
 
+                "glob" => Value::builtin("glob", |args, env| {
+                    check_args_len(env.get("glob")?, args, 1)?;
+                    let pattern = match args[0].eval(env)? {
+                        Value::Path(path) => path.to_string_lossy().to_string(),
+                        Value::String(pattern) | Value::Symbol(pattern) => pattern,
+                        _ => return Err(Error::InvalidArguments(env.get("glob")?, args.clone()))
+                    };
+                    Ok(Value::List(process::glob(&pattern, env)?.into_iter().map(Value::Path).collect()))
+                }),
+
+                "which" => Value::builtin("which", |args, env| {
+                    check_args_len(env.get("which")?, args, 1)?;
+                    match args[0].eval(env)? {
+                        Value::Path(path) => Ok(match process::which(&path.to_string_lossy(), env)? {
+                            Some(path) => Value::Path(path),
+                            None => Value::Nil
+                        }),
+                        Value::String(name) | Value::Symbol(name) => Ok(match process::which(&name, env)? {
+                            Some(path) => Value::Path(path),
+                            None => Value::Nil
+                        }),
+                        _ => Err(Error::InvalidArguments(env.get("which")?, args.clone()))
+                    }
+                }),
+
+                "capture" => Value::builtin("capture", |args, env| {
+                    let (program, arguments, options) = program_args(env.get("capture")?, args, env, &["trim", "stdin", "timeout"])?;
+                    let trim = match options.get("trim") {
+                        Some(Value::Boolean(b)) => *b,
+                        None => true,
+                        _ => return Err(Error::InvalidArguments(env.get("capture")?, args.clone()))
+                    };
+                    let timeout = match options.get("timeout") {
+                        Some(timeout) => process::timeout(timeout).map_err(|_| Error::InvalidArguments(Value::symbol("capture"), args.clone()))?,
+                        None => env.get_timeout()?
+                    };
+
+                    let mut cmd = process::command(&program, &arguments, env)?;
+                    let output = process::output(
+                        cmd.stdin(Stdio::inherit()).stdout(Stdio::piped()).stderr(Stdio::inherit()),
+                        options.get("stdin").map(process::input),
+                        timeout
+                    );
+                    process::check_interrupt()?;
+
+                    match output {
+                        Ok(output) if output.status.success() => {
+                            let mut result = String::from_utf8_lossy(&output.stdout).to_string();
+                            if trim {
+                                if result.ends_with('\n') { result.pop(); }
+                                if result.ends_with('\r') { result.pop(); }
+                            }
+                            Ok(Value::String(result))
+                        }
+                        Ok(output) => Err(process::failed(&program, &arguments, process::exit_code(&output.status))),
+                        Err(e) if e.kind() == ErrorKind::TimedOut => Err(process::timed_out(process::describe(program, arguments), timeout)),
+                        Err(_) => Err(Error::CannotExecuteProgram(program))
+                    }
+                }),
+
+                "proc" => {
+                    let mut proc = BTreeMap::new();
+                    proc.insert("run".to_string(), Value::builtin("proc@run", |args, env| {
+                        let (program, arguments, options) = program_args(Value::symbol("proc@run"), args, env, &["cwd", "stdin", "timeout"])?;
+
+                        let mut cmd = process::command(&program, &arguments, env)?;
+                        match options.get("cwd") {
+                            Some(Value::Path(path)) => { cmd.current_dir(env.get_cwd()?.join(path)); }
+                            Some(Value::String(path)) | Some(Value::Symbol(path)) => { cmd.current_dir(env.get_cwd()?.join(path)); }
+                            None => {}
+                            _ => return Err(Error::InvalidArguments(Value::symbol("proc@run"), args.clone()))
+                        }
+                        let timeout = match options.get("timeout") {
+                            Some(timeout) => process::timeout(timeout).map_err(|_| Error::InvalidArguments(Value::symbol("proc@run"), args.clone()))?,
+                            None => env.get_timeout()?
+                        };
+
+                        let start = Instant::now();
+                        let output = match process::output(cmd.stdin(Stdio::inherit()).stdout(Stdio::piped()).stderr(Stdio::piped()), options.get("stdin").map(process::input), timeout) {
+                            Ok(output) => output,
+                            Err(e) if e.kind() == ErrorKind::TimedOut => return Err(process::timed_out(process::describe(program, arguments), timeout)),
+                            Err(_) => return Err(Error::CannotExecuteProgram(program))
+                        };
+                        process::check_interrupt()?;
+
+                        let mut result = BTreeMap::new();
+                        result.insert("status".to_string(), match output.status.code() {
+                            Some(code) => Value::Integer(code),
+                            None => Value::Nil
+                        });
+                        result.insert("signal".to_string(), match process::signal(&output.status) {
+                            Some(signal) => Value::Integer(signal),
+                            None => Value::Nil
+                        });
+                        result.insert("success".to_string(), Value::Boolean(output.status.success()));
+                        result.insert("stdout".to_string(), Value::String(String::from_utf8_lossy(&output.stdout).to_string()));
+                        result.insert("stderr".to_string(), Value::String(String::from_utf8_lossy(&output.stderr).to_string()));
+                        result.insert("duration".to_string(), Value::Float(start.elapsed().as_secs_f64()));
+                        Ok(Value::Table(result))
+                    }));
+
+                    proc.insert("spawn".to_string(), Value::builtin("proc@spawn", |args, env| {
+                        let (program, arguments, options) = program_args(Value::symbol("proc@spawn"), args, env, &["cwd", "stdin"])?;
+
+                        let mut cmd = process::command(&program, &arguments, env)?;
+                        match options.get("cwd") {
+                            Some(Value::Path(path)) => { cmd.current_dir(env.get_cwd()?.join(path)); }
+                            Some(Value::String(path)) | Some(Value::Symbol(path)) => { cmd.current_dir(env.get_cwd()?.join(path)); }
+                            None => {}
+                            _ => return Err(Error::InvalidArguments(Value::symbol("proc@spawn"), args.clone()))
+                        }
+
+                        let description = process::describe(program, arguments).to_string();
+                        let mut stage = process::Stage::new(cmd);
+                        stage.input = options.get("stdin").map(process::input);
+                        Ok(Value::Job(process::background(stage, description)?))
+                    }));
+
+                    Value::Table(proc)
+                }
+
+                "environ" => {
+                    let mut environment = BTreeMap::new();
+                    environment.insert("get".to_string(), Value::builtin("environ@get", |args, env| {
+                        check_args_len(Value::symbol("environ@get"), args, 1)?;
+                        match args[0].eval(env)? {
+                            Value::String(name) | Value::Symbol(name) => Ok(match var(name) {
+                                Ok(value) => Value::String(value),
+                                Err(_) => Value::Nil
+                            }),
+                            _ => Err(Error::InvalidArguments(Value::symbol("environ@get"), args.clone()))
+                        }
+                    }));
+
+                    environment.insert("set".to_string(), Value::builtin("environ@set", |args, env| {
+                        check_args_len(Value::symbol("environ@set"), args, 2)?;
+                        match (args[0].eval(env)?, args[1].eval(env)?) {
+                            (Value::String(name), value) | (Value::Symbol(name), value) if !name.is_empty() && !name.contains('=') => {
+                                set_var(name, value.to_string());
+                                Ok(Value::Nil)
+                            }
+                            _ => Err(Error::InvalidArguments(Value::symbol("environ@set"), args.clone()))
+                        }
+                    }));
+
+                    environment.insert("unset".to_string(), Value::builtin("environ@unset", |args, env| {
+                        check_args_len(Value::symbol("environ@unset"), args, 1)?;
+                        match args[0].eval(env)? {
+                            Value::String(name) | Value::Symbol(name) if !name.is_empty() && !name.contains('=') => {
+                                remove_var(name);
+                                Ok(Value::Nil)
+                            }
+                            _ => Err(Error::InvalidArguments(Value::symbol("environ@unset"), args.clone()))
+                        }
+                    }));
+
+                    environment.insert("vars".to_string(), Value::builtin("environ@vars", |args, _| {
+                        check_args_len(Value::symbol("environ@vars"), args, 0)?;
+                        Ok(Value::Table(vars().map(|(name, value)| (name, Value::String(value))).collect()))
+                    }));
+
+                    Value::Table(environment)
+                }
+
+                "with-env" => Value::builtin("with-env", |args, env| {
+                    check_args_len(env.get("with-env")?, args, 2)?;
+                    let overrides = match args[0].eval(env)? {
+                        Value::Table(overrides) => overrides,
+                        _ => return Err(Error::InvalidArguments(env.get("with-env")?, args.clone()))
+                    };
+
+                    // Nested overrides are added on top of the ones already in effect
+                    let saved = env.symbols.get(ENV_VARS).cloned();
+                    let mut vars = match &saved {
+                        Some(Value::Table(vars)) => vars.clone(),
+                        _ => BTreeMap::new()
+                    };
+                    vars.extend(overrides);
+                    env.define(ENV_VARS, Value::Table(vars));
+
+                    let result = Value::Apply(Box::new(args[1].clone()), vec![]).eval(env);
+
+                    match saved {
+                        Some(vars) => env.define(ENV_VARS, vars),
+                        None => { env.symbols.remove(ENV_VARS); }
+                    }
+                    result
+                }),
+
                 "cards" => CARDS.clone(),
                 "chess" => CHESS.clone(),
 
@@ -1185,6 +1530,58 @@ impl Environment {
                     }))
                 }),
 
+                "throw" => Value::builtin("throw", |args, env| {
+                    check_args_len(env.get("throw")?, args, 1)?;
+
+                    match args[0].eval(env)? {
+                        // Errors are thrown again as they are, so their kind is kept
+                        Value::Error(e) => Err(*e),
+                        value => Err(Error::Thrown(value))
+                    }
+                }),
+
+                "source" => Value::builtin("source", |args, env| {
+                    check_args_len(env.get("source")?, args, 1)?;
+
+                    let path = match args[0].eval(env)? {
+                        Value::Path(path) => path,
+                        Value::String(path) | Value::Symbol(path) => PathBuf::from(path),
+                        _ => return Err(Error::InvalidArguments(Value::symbol("source"), args.clone()))
+                    };
+
+                    // The file is run in the caller's environment, so its definitions are kept
+                    match read_to_string(env.get_cwd()?.join(&path)) {
+                        Ok(contents) => parse(contents)?.eval(env),
+                        Err(_) => Err(Error::CustomError(format!("could not read file {:?}", path)))
+                    }
+                }),
+
+                "eval" => Value::builtin("eval", |args, env| {
+                    check_args_len(env.get("eval")?, args, 1)?;
+
+                    match args[0].eval(env)? {
+                        Value::String(code) => match parse(code) {
+                            Ok(program) => program.eval(env),
+                            Err(e) => Ok(Value::Error(Box::new(e)))
+                        },
+                        _ => Err(Error::InvalidArguments(Value::symbol("eval"), args.clone()))
+                    }
+                }),
+
+                "import" => Value::builtin("import", |args, env| {
+                    check_args_len(env.get("import")?, args, 1)?;
+
+                    let name = match args[0].eval(env)? {
+                        Value::String(name) | Value::Symbol(name) => name,
+                        _ => return Err(Error::InvalidArguments(Value::symbol("import"), args.clone()))
+                    };
+
+                    match find_module(&name, env)? {
+                        Some(path) => import(path, env),
+                        None => Err(Error::CustomError(format!("could not find module {:?}", name)))
+                    }
+                }),
+
 
                 "widget" => {
                     let mut widget = BTreeMap::new();
@@ -1329,13 +1726,82 @@ impl Environment {
                 "fmt" => FMT.clone(),
                 "math" => MATH.clone(),
 
+                "jobs" => Value::builtin("jobs", |args, env| {
+                    check_args_len(env.get("jobs")?, args, 0)?;
+                    for job in process::list_jobs() {
+                        println!("{}", job);
+                    }
+                    Ok(Value::Nil)
+                }),
+
+                "fg" => Value::builtin("fg", |args, env| {
+                    let id = match args.len() {
+                        0 => match process::job_ids().last() {
+                            Some(id) => *id,
+                            None => return Err(Error::CustomError(String::from("there are no jobs")))
+                        },
+                        _ => job_arg(env.get("fg")?, args, env)?
+                    };
+                    // The job may have already finished, in which case there's nothing to resume.
+                    // Its input is closed, so this only waits for it instead of giving it the terminal
+                    let _ = process::signal_job(id, process::SIGCONT);
+                    Ok(Value::Integer(process::wait_job(id, true)?))
+                }),
+
+                "bg" => Value::builtin("bg", |args, env| {
+                    let id = job_arg(env.get("bg")?, args, env)?;
+                    process::signal_job(id, process::SIGCONT)?;
+                    Ok(Value::Nil)
+                }),
+
+                "wait" => Value::builtin("wait", |args, env| {
+                    if args.is_empty() {
+                        for id in process::job_ids() {
+                            process::wait_job(id, false)?;
+                        }
+                        Ok(Value::Nil)
+                    } else {
+                        let id = job_arg(env.get("wait")?, args, env)?;
+                        Ok(Value::Integer(process::wait_job(id, false)?))
+                    }
+                }),
+
+                "kill" => Value::builtin("kill", |args, env| {
+                    if args.is_empty() {
+                        return Err(Error::TooFewArguments(env.get("kill")?, args.clone()))
+                    } else if args.len() > 2 {
+                        return Err(Error::TooManyArguments(env.get("kill")?, args.clone()))
+                    }
+
+                    let id = job_arg(env.get("kill")?, &args[..1], env)?;
+                    let signal = match args.get(1).map(|arg| arg.eval(env)).transpose()? {
+                        None => process::signal_number("TERM"),
+                        Some(Value::Integer(n)) => Some(n),
+                        Some(Value::String(name)) | Some(Value::Symbol(name)) => process::signal_number(&name),
+                        _ => None
+                    };
+
+                    match signal {
+                        Some(signal) => process::signal_job(id, signal)?,
+                        None => return Err(Error::InvalidArguments(env.get("kill")?, args.clone()))
+                    }
+                    Ok(Value::Nil)
+                }),
+
                 "sleep" => Value::builtin("sleep", |args, env| {
                     check_args_len(env.get("sleep")?, &args, 1)?;
 
-                    match args[0].eval(env)? {
-                        Value::Float(n)   => sleep(Duration::from_millis((n.abs() * 1000.0) as u64)),
-                        Value::Integer(n) => sleep(Duration::from_millis((n.abs() * 1000) as u64)),
+                    let duration = match args[0].eval(env)? {
+                        Value::Float(n)   => Duration::from_millis((n.abs() * 1000.0) as u64),
+                        Value::Integer(n) => Duration::from_millis((n.abs() * 1000) as u64),
                         _ => return Err(Error::InvalidArguments(env.get("sleep")?, args.clone()))
+                    };
+
+                    // Sleep in short steps, so that CTRL+C can interrupt a long sleep
+                    let end = Instant::now() + duration;
+                    while let Some(remaining) = end.checked_duration_since(Instant::now()) {
+                        process::check_interrupt()?;
+                        sleep(remaining.min(Duration::from_millis(50)));
                     }
                     
                     Ok(Value::Nil)
@@ -1530,7 +1996,29 @@ impl Environment {
                 "docs" => Value::Macro(vec![], Box::new(Value::Define(CWD.to_string(), Box::new(self.get(DOCUMENTS)?)))),
                 "down" => Value::Macro(vec![], Box::new(Value::Define(CWD.to_string(), Box::new(self.get(DOWNLOADS)?)))),
 
-                "exit" | "quit" => Value::builtin("exit", |_, _| exit(0)),
+                "exit" | "quit" => Value::builtin("exit", |args, env| {
+                    let code = match args.len() {
+                        0 => 0,
+                        1 => match args[0].eval(env)? {
+                            Value::Integer(code) => code,
+                            _ => return Err(Error::InvalidArguments(env.get("exit")?, args.clone()))
+                        },
+                        _ => return Err(Error::TooManyArguments(env.get("exit")?, args.clone()))
+                    };
+                    run_exit_hooks(env);
+                    exit(code)
+                }),
+
+                "on-exit" => Value::builtin("on-exit", |args, env| {
+                    check_args_len(env.get("on-exit")?, args, 1)?;
+                    match args[0].eval(env)? {
+                        hook @ Value::Lambda(..) | hook @ Value::Macro(..) | hook @ Value::Builtin(..) => {
+                            EXIT_HOOKS.lock().unwrap().push(hook);
+                            Ok(Value::Nil)
+                        }
+                        _ => Err(Error::InvalidArguments(env.get("on-exit")?, args.clone()))
+                    }
+                }),
 
                 "unbind" => Value::builtin("unbind", |args, env| {
                     check_args_len(env.get("unbind")?, &args, 1)?;
@@ -1834,22 +2322,22 @@ impl Environment {
 
                 "back" => Value::Macro(vec![], Box::new(Value::Apply(Box::new(Value::Symbol("cd".to_string())), vec![Value::String("..".to_string())]))),
 
-                "add" => Value::Lambda(vec!["x".to_string(), "y".to_string()], Box::new(Value::Add(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
-                "mul" => Value::Lambda(vec!["x".to_string(), "y".to_string()], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
-                "sub" => Value::Lambda(vec!["x".to_string(), "y".to_string()], Box::new(Value::Subtract(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
-                "div" => Value::Lambda(vec!["x".to_string(), "y".to_string()], Box::new(Value::Divide(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
-                "rem" => Value::Lambda(vec!["x".to_string(), "y".to_string()], Box::new(Value::Remainder(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
+                "add" => Value::Lambda(vec![Param::new("x"), Param::new("y")], Box::new(Value::Add(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
+                "mul" => Value::Lambda(vec![Param::new("x"), Param::new("y")], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
+                "sub" => Value::Lambda(vec![Param::new("x"), Param::new("y")], Box::new(Value::Subtract(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
+                "div" => Value::Lambda(vec![Param::new("x"), Param::new("y")], Box::new(Value::Divide(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
+                "rem" => Value::Lambda(vec![Param::new("x"), Param::new("y")], Box::new(Value::Remainder(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Symbol("y".to_string())))), Self::new()),
 
-                "sum" => Value::Lambda(vec!["x".to_string()], Box::new(Value::Apply(Box::new(Value::Symbol("reduce".to_string())), vec![Value::Symbol("add".to_string()), Value::Integer(0), Value::Symbol("x".to_string())])), Self::new()),
-                "prod" => Value::Lambda(vec!["x".to_string()], Box::new(Value::Apply(Box::new(Value::Symbol("reduce".to_string())), vec![Value::Symbol("mul".to_string()), Value::Integer(1), Value::Symbol("x".to_string())])), Self::new()),
+                "sum" => Value::Lambda(vec![Param::new("x")], Box::new(Value::Apply(Box::new(Value::Symbol("reduce".to_string())), vec![Value::Symbol("add".to_string()), Value::Integer(0), Value::Symbol("x".to_string())])), Self::new()),
+                "prod" => Value::Lambda(vec![Param::new("x")], Box::new(Value::Apply(Box::new(Value::Symbol("reduce".to_string())), vec![Value::Symbol("mul".to_string()), Value::Integer(1), Value::Symbol("x".to_string())])), Self::new()),
 
-                "inc" => Value::Lambda(vec!["x".to_string()], Box::new(Value::Add(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(1)))), Self::new()),
-                "dec" => Value::Lambda(vec!["x".to_string()], Box::new(Value::Subtract(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(1)))), Self::new()),
+                "inc" => Value::Lambda(vec![Param::new("x")], Box::new(Value::Add(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(1)))), Self::new()),
+                "dec" => Value::Lambda(vec![Param::new("x")], Box::new(Value::Subtract(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(1)))), Self::new()),
                 
-                "double" => Value::Lambda(vec!["x".to_string()], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(2)))), Self::new()),
-                "triple" => Value::Lambda(vec!["x".to_string()], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(3)))), Self::new()),
-                "quadruple" => Value::Lambda(vec!["x".to_string()], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(4)))), Self::new()),
-                "quintuple" => Value::Lambda(vec!["x".to_string()], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(5)))), Self::new()),
+                "double" => Value::Lambda(vec![Param::new("x")], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(2)))), Self::new()),
+                "triple" => Value::Lambda(vec![Param::new("x")], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(3)))), Self::new()),
+                "quadruple" => Value::Lambda(vec![Param::new("x")], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(4)))), Self::new()),
+                "quintuple" => Value::Lambda(vec![Param::new("x")], Box::new(Value::Multiply(Box::new(Value::Symbol("x".to_string())), Box::new(Value::Integer(5)))), Self::new()),
 
                 x => {
                     for t in TYPES {
@@ -1898,6 +2386,23 @@ impl Environment {
         }
     }
 
+    /// Get the time limit for running programs, set by the `TIMEOUT` symbol.
+    pub(crate) fn get_timeout(&self) -> Result<Option<Duration>, Error> {
+        process::timeout(&self.get(TIMEOUT)?)
+    }
+
+    /// Get the environment variables that programs run in this environment should have,
+    /// in addition to the variables inherited from the shell. A value of `None` removes the variable.
+    pub(crate) fn get_env_vars(&self) -> BTreeMap<String, Option<String>> {
+        match self.symbols.get(ENV_VARS) {
+            Some(Value::Table(vars)) => vars.iter().map(|(name, value)| (name.clone(), match value {
+                Value::Nil => None,
+                value => Some(value.to_string())
+            })).collect(),
+            _ => BTreeMap::new()
+        }
+    }
+
     pub(crate) fn get_symbols(&self) -> &BTreeMap<String, Value> {
         &self.symbols
     }
@@ -24,6 +24,8 @@ pub enum Error {
     InvalidArguments(Value, Vec<Value>),
     TooFewArguments(Value,  Vec<Value>),
     TooManyArguments(Value, Vec<Value>),
+    UnknownParameter(Value, String),
+    DuplicateArgument(Value, String),
 
     CannotApply(Value),
 
@@ -32,7 +34,15 @@ pub enum Error {
 
     InvalidCondition(Value),
     CannotIterateOver(Value),
+    NoMatchingPattern(Value),
+    CannotDestructure(Value, String),
     CannotExecuteProgram(Value),
+    /// A program that doesn't exist, which is given to the `command-not-found` hook along with its arguments.
+    ProgramNotFound(Value, Vec<Value>),
+    ProgramFailed(Value, Vec<Value>, i32),
+    JobNotFound(usize),
+    CommandTimedOut(Value, f64),
+    Interrupted,
 
     CannotChangeDir(PathBuf),
 
@@ -49,10 +59,63 @@ pub enum Error {
 
     SyntaxError(String),
     CustomError(String),
+    Thrown(Value),
 }
 
 
 
+impl Error {
+    /// The name of this kind of error, as given to `catch` blocks.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::SymbolNotDefined(..) => "SymbolNotDefined",
+            Self::CannotNegate(..) => "CannotNegate",
+            Self::CannotAdd(..) => "CannotAdd",
+            Self::CannotSubtract(..) => "CannotSubtract",
+            Self::CannotMultiply(..) => "CannotMultiply",
+            Self::CannotDivide(..) => "CannotDivide",
+            Self::CannotRemainder(..) => "CannotRemainder",
+            Self::CannotOrder(..) => "CannotOrder",
+            Self::CannotCompare(..) => "CannotCompare",
+            Self::CannotRange(..) => "CannotRange",
+            Self::CannotAnd(..) => "CannotAnd",
+            Self::CannotOr(..) => "CannotOr",
+            Self::CannotNot(..) => "CannotNot",
+            Self::InvalidArguments(..) => "InvalidArguments",
+            Self::TooFewArguments(..) => "TooFewArguments",
+            Self::TooManyArguments(..) => "TooManyArguments",
+            Self::UnknownParameter(..) => "UnknownParameter",
+            Self::DuplicateArgument(..) => "DuplicateArgument",
+            Self::CannotApply(..) => "CannotApply",
+            Self::CannotIndexWith(..) => "CannotIndexWith",
+            Self::IndexNotFound(..) => "IndexNotFound",
+            Self::InvalidCondition(..) => "InvalidCondition",
+            Self::CannotIterateOver(..) => "CannotIterateOver",
+            Self::NoMatchingPattern(..) => "NoMatchingPattern",
+            Self::CannotDestructure(..) => "CannotDestructure",
+            Self::CannotExecuteProgram(..) => "CannotExecuteProgram",
+            Self::ProgramNotFound(..) => "ProgramNotFound",
+            Self::ProgramFailed(..) => "ProgramFailed",
+            Self::JobNotFound(..) => "JobNotFound",
+            Self::CommandTimedOut(..) => "CommandTimedOut",
+            Self::Interrupted => "Interrupted",
+            Self::CannotChangeDir(..) => "CannotChangeDir",
+            Self::ReadInputError => "ReadInputError",
+            Self::CouldNotParseFloat(..) => "CouldNotParseFloat",
+            Self::CouldNotParseInteger(..) => "CouldNotParseInteger",
+            Self::HomeDirectoryNotFound => "HomeDirectoryNotFound",
+            Self::DocumentsDirectoryNotFound => "DocumentsDirectoryNotFound",
+            Self::DesktopDirectoryNotFound => "DesktopDirectoryNotFound",
+            Self::DownloadsDirectoryNotFound => "DownloadsDirectoryNotFound",
+            Self::VideosDirectoryNotFound => "VideosDirectoryNotFound",
+            Self::PicturesDirectoryNotFound => "PicturesDirectoryNotFound",
+            Self::SyntaxError(..) => "SyntaxError",
+            Self::CustomError(..) => "CustomError",
+            Self::Thrown(..) => "Thrown",
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
@@ -79,13 +142,22 @@ impl fmt::Display for Error {
             Self::InvalidArguments(func, args) => write!(f, "invalid arguments in call `{:?}`", Value::Apply(Box::new(func.clone()), args.clone())),
             Self::TooFewArguments(func, args) => write!(f, "too few arguments in call `{:?}`", Value::Apply(Box::new(func.clone()), args.clone())),
             Self::TooManyArguments(func, args) => write!(f, "too many arguments in call `{:?}`", Value::Apply(Box::new(func.clone()), args.clone())),
+            Self::UnknownParameter(func, name) => write!(f, "`{:?}` has no parameter named `{}`", func, name),
+            Self::DuplicateArgument(func, name) => write!(f, "parameter `{}` of `{:?}` was given more than one argument", name, func),
 
             Self::CannotIndexWith(val, idx) => write!(f, "cannot index `{:?}` with `{:?}`", val, idx),
             Self::CannotApply(val) => write!(f, "cannot apply `{:?}`", val),
 
             Self::InvalidCondition(val) => write!(f, "invalid condition `{:?}`", val),
             Self::CannotIterateOver(val) => write!(f, "cannot iterate over `{:?}`", val),
+            Self::NoMatchingPattern(val) => write!(f, "no pattern matched `{:?}`", val),
+            Self::CannotDestructure(val, reason) => write!(f, "cannot destructure `{:?}`: {}", val, reason),
             Self::CannotExecuteProgram(prog) => write!(f, "cannot execute program `{:?}`", prog),
+            Self::ProgramNotFound(prog, _) => write!(f, "program `{:?}` not found", prog),
+            Self::ProgramFailed(prog, args, code) => write!(f, "program `{}` exited with status {}", Value::Run(Box::new(prog.clone()), args.clone()), code),
+            Self::JobNotFound(id) => write!(f, "job {} not found", Value::Job(*id)),
+            Self::CommandTimedOut(command, seconds) => write!(f, "command `{}` timed out after {} seconds", command, seconds),
+            Self::Interrupted => write!(f, "interrupted"),
 
             Self::CannotChangeDir(dir) => write!(f, "cannot change dir {:?}", dir),
             Self::ReadInputError => write!(f, "could not get user input"),
@@ -102,6 +174,7 @@ impl fmt::Display for Error {
 
             Self::SyntaxError(s) => write!(f, "{}", s),
             Self::CustomError(s) => write!(f, "{}", s),
+            Self::Thrown(value) => write!(f, "{}", value),
         }
     }
 }
@@ -132,13 +205,22 @@ impl fmt::Debug for Error {
             Self::InvalidArguments(func, args) => write!(f, "invalid arguments in call `{:?}`", Value::Apply(Box::new(func.clone()), args.clone())),
             Self::TooFewArguments(func, args) => write!(f, "too few arguments in call `{:?}`", Value::Apply(Box::new(func.clone()), args.clone())),
             Self::TooManyArguments(func, args) => write!(f, "too many arguments in call `{:?}`", Value::Apply(Box::new(func.clone()), args.clone())),
+            Self::UnknownParameter(func, name) => write!(f, "`{:?}` has no parameter named `{}`", func, name),
+            Self::DuplicateArgument(func, name) => write!(f, "parameter `{}` of `{:?}` was given more than one argument", name, func),
 
             Self::CannotApply(val) => write!(f, "cannot apply `{:?}`", val),
             Self::CannotIndexWith(val, idx) => write!(f, "cannot index `{:?}` with `{:?}`", val, idx),
 
             Self::InvalidCondition(val) => write!(f, "invalid condition `{:?}`", val),
             Self::CannotIterateOver(val) => write!(f, "cannot iterate over `{:?}`", val),
+            Self::NoMatchingPattern(val) => write!(f, "no pattern matched `{:?}`", val),
+            Self::CannotDestructure(val, reason) => write!(f, "cannot destructure `{:?}`: {}", val, reason),
             Self::CannotExecuteProgram(prog) => write!(f, "cannot execute program `{:?}`", prog),
+            Self::ProgramNotFound(prog, _) => write!(f, "program `{:?}` not found", prog),
+            Self::ProgramFailed(prog, args, code) => write!(f, "program `{}` exited with status {}", Value::Run(Box::new(prog.clone()), args.clone()), code),
+            Self::JobNotFound(id) => write!(f, "job {} not found", Value::Job(*id)),
+            Self::CommandTimedOut(command, seconds) => write!(f, "command `{}` timed out after {} seconds", command, seconds),
+            Self::Interrupted => write!(f, "interrupted"),
             
             Self::CannotChangeDir(dir) => write!(f, "cannot change dir {:?}", dir),
             Self::ReadInputError => write!(f, "could not get user input"),
@@ -155,6 +237,7 @@ impl fmt::Debug for Error {
 
             Self::SyntaxError(s) => write!(f, "{}", s),
             Self::CustomError(s) => write!(f, "{}", s),
+            Self::Thrown(value) => write!(f, "{}", value),
         }
     }
 }
\ No newline at end of file
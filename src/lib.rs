@@ -1,19 +1,26 @@
 pub const VERSION: &[usize] = &[0, 1, 1];
 pub const PRELUDE_FILENAME: &str = ".atom-prelude";
+pub const CONFIG_DIRECTORY: &str = ".config/atom/conf.d";
 pub const HISTORY_FILENAME: &str = ".atom-history";
 use std::cmp::min;
 
 pub use asciicolor::Colorize;
 
 mod env;
-pub use env::{CWD, Environment, REPORT, PROMPT, INCOMPLETE_PROMPT};
+pub use env::{CWD, Environment, run_exit_hooks, REPORT, PROMPT, INCOMPLETE_PROMPT, PIPEFAIL, COMMAND_NOT_FOUND, TIMEOUT, GLOB, STRICT, LAST_STATUS, LAST_DURATION, LAST_ERROR, ARGS, SCRIPT};
 
 mod value;
-pub use value::{Value, Size};
+pub use value::{Value, Size, Redirection, Param};
+
+mod pattern;
+pub use pattern::Pattern;
 
 mod error;
 pub use error::Error;
 
+mod process;
+pub use process::{finished_jobs, interrupt, clear_interrupt};
+
 use comment::atom::strip;
 
 use lalrpop_util::{lalrpop_mod, ParseError};
@@ -21,7 +28,11 @@ lalrpop_mod!(parser);
 use parser::ProgramParser;
 
 pub fn parse(code: impl ToString) -> Result<Value, Error> {
-    let code = code.to_string();
+    let mut code = code.to_string();
+    // A shebang line is only meaningful to the operating system, the newline is kept to preserve line numbers
+    if code.starts_with("#!") {
+        code = code.find('\n').map(|i| code[i..].to_string()).unwrap_or_default();
+    }
     let code = match strip(&code) {
         Ok(s) => s,
         Err(_) => code.clone(),
@@ -33,16 +44,90 @@ pub fn parse(code: impl ToString) -> Result<Value, Error> {
     }
 }
 
-pub type SyntaxError<'a, T> = ParseError<usize, T, &'a str>;
+/// Replace the escape sequences in the body of a string literal with the characters they stand for.
+fn unescape(s: &str) -> String {
+    s.replace("\\\\", "\\").replace("\\\"", "\"").replace("\\n", "\n").replace("\\r", "\r").replace("\\t", "\t").replace("\\v", &String::from(13 as char)).replace("\\a", &String::from(7 as char)).replace("\\b", &String::from(8 as char))
+}
+
+/// Split the body of an interpolated string literal into its text and the expressions between braces.
+/// Braces are written literally as `{{` and `}}`. An error is returned with the offset in the body it was found at.
+fn interpolate(body: &str) -> Result<Value, (usize, &'static str)> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = body.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        match ch {
+            '{' if matches!(chars.peek(), Some((_, '{'))) => { chars.next(); text.push('{') }
+            '}' if matches!(chars.peek(), Some((_, '}'))) => { chars.next(); text.push('}') }
+            '{' => {
+                // The expression ends at the matching brace, so it can contain blocks and tables.
+                // Braces inside its strings don't count, which are quoted with `\"` to stay inside the literal
+                let mut expr = String::new();
+                let mut depth = 1;
+                let mut quoted = false;
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => {
+                            expr.push('\\');
+                            if let Some((_, ch)) = chars.next() {
+                                quoted ^= ch == '"';
+                                expr.push(ch)
+                            }
+                        }
+                        Some((_, '}')) if depth == 1 && !quoted => break,
+                        Some((_, ch)) => {
+                            match ch {
+                                '{' if !quoted => depth += 1,
+                                '}' if !quoted => depth -= 1,
+                                _ => {}
+                            }
+                            expr.push(ch)
+                        }
+                        None => return Err((start, "unclosed `{` in interpolated string"))
+                    }
+                }
+
+                if !text.is_empty() {
+                    parts.push(Value::String(unescape(&text)));
+                    text.clear();
+                }
+                parts.push(match ProgramParser::new().parse(&expr.replace("\\\"", "\"")) {
+                    Ok(Value::Do(mut exprs)) if exprs.len() == 1 => exprs.remove(0),
+                    _ => return Err((start, "invalid expression in interpolated string"))
+                });
+            }
+            '}' => return Err((start, "unmatched `}` in interpolated string")),
+            // Keep escape sequences together so that they're replaced along with the rest of the text
+            '\\' => {
+                text.push(ch);
+                text.extend(chars.next().map(|(_, ch)| ch));
+            }
+            _ => text.push(ch)
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(Value::String(unescape(&text)));
+    }
+    Ok(Value::Interpolate(parts))
+}
+
+pub type SyntaxError<'a, T> = ParseError<usize, T, (usize, &'a str)>;
 
 /// This formats an error properly given the line, the `unexpected` token as a string,
 /// the line number, and the column number of the unexpected token.
 pub fn make_error(line: &str, unexpected: &str, line_number: usize, column_number: usize) -> String {
+    let note = format!("unexpected `{}`", unexpected.bright_yellow().underline());
+    annotate(line, unexpected.len(), line_number, column_number, &note)
+}
+
+/// Underline part of a line, and explain what's wrong with it in a note below.
+fn annotate(line: &str, width: usize, line_number: usize, column_number: usize, note: &str) -> String {
     // The string used to underline the unexpected token
     let underline = format!(
         "{}^{}",
         " ".repeat(column_number),
-        "-".repeat(unexpected.len() - 1)
+        "-".repeat(width.max(1) - 1)
     );
 
     // Format string properly and return
@@ -51,12 +136,12 @@ pub fn make_error(line: &str, unexpected: &str, line_number: usize, column_numbe
 {line_number} | {line}
 {WS} | {underline}
 {WS} |
-{WS} = unexpected `{unexpected}`",
+{WS} = {note}",
         WS = " ".repeat(line_number.to_string().len()),
         line_number = line_number,
         line = line.bright_yellow().underline(),
         underline = underline,
-        unexpected = unexpected.bright_yellow().underline()
+        note = note
     )
 }
 
@@ -134,10 +219,46 @@ pub fn format_error<T: core::fmt::Debug>(script: &str, err: SyntaxError<T>) -> S
 
             make_error(&line, unexpected, line_number, column)
         }
-        SyntaxError::User { error } => format!(
-            "  |\n? | {}\n  | {}\n  |\n  = unexpected compiling error",
-            error,
-            format!("^{}", "-".repeat(error.len() - 1))
-        ),
+        SyntaxError::User { error: (location, error) } => {
+            let (line_number, line, column) = get_line(script, location);
+            annotate(&line, 1, line_number, column, error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{interpolate, parse, Environment, Value};
+
+    fn format(code: &str) -> String {
+        let mut env = Environment::new();
+        match parse(code).and_then(|program| program.eval(&mut env)).unwrap() {
+            Value::String(s) => s,
+            other => panic!("expected a string, got {}", other)
+        }
+    }
+
+    #[test]
+    fn interpolated_strings_mix_text_and_expressions() {
+        assert_eq!(format(r#"x := 2; f"{x} + {x} = {x + x}""#), "2 + 2 = 4");
+        assert_eq!(format(r#"f"{{literal}} {{{1}}}\t""#), "{literal} {1}\t");
+        assert_eq!(format(r#"f"{ if 1 < 2 { \"yes\" } else { \"no\" } }""#), "yes");
+    }
+
+    #[test]
+    fn braces_in_interpolated_strings_are_skipped() {
+        assert_eq!(format(r#"f"<{ \"}\" }>""#), "<}>");
+        assert_eq!(format(r#"f"{ \"{\" + \"}}\" }""#), "{}}");
+    }
+
+    #[test]
+    fn interpolation_errors_have_an_offset() {
+        assert_eq!(interpolate("ok {x").unwrap_err(), (3, "unclosed `{` in interpolated string"));
+        assert_eq!(interpolate("ok } x").unwrap_err(), (3, "unmatched `}` in interpolated string"));
+        assert_eq!(interpolate("{x} {1 +}").unwrap_err(), (4, "invalid expression in interpolated string"));
+
+        let error = parse("x := 1;\ny := f\"ok {x\";").unwrap_err().to_string();
+        assert!(error.contains("unclosed `{` in interpolated string"), "{}", error);
+        assert!(!error.contains('?'), "{}", error);
     }
 }
@@ -0,0 +1,288 @@
+use super::{Value, Error};
+use std::{collections::BTreeMap, fmt};
+
+/// The names that match any value of a type in a pattern, instead of binding it.
+const TYPES: &[&str] = &["int", "float", "bool", "str", "path", "size", "list", "table", "err"];
+
+/// A pattern that a value can be matched against, in the arms of a `match` expression.
+#[derive(Clone, PartialEq)]
+pub enum Pattern {
+    /// `_` matches anything.
+    Wildcard,
+    /// A name matches anything, and binds the value to it.
+    Bind(String),
+    /// A literal matches values equal to it.
+    Literal(Value),
+    /// A type tag matches any value of that type, and optionally binds it with `name: tag`.
+    Type(String, Option<String>),
+    /// A list pattern matches its items in order, and the rest of the list with `...rest`.
+    List(Vec<Self>, Option<Box<Self>>),
+    /// A table pattern matches tables with each of its keys, ignoring the others.
+    Table(BTreeMap<String, Self>),
+    /// `...` or `...name`, which is only allowed at the end of a list pattern.
+    Rest(Option<String>),
+}
+
+impl Pattern {
+    /// Read a name in a `match` arm, where the names of types are type tags.
+    pub(crate) fn symbol(name: &str) -> Self {
+        if TYPES.contains(&name) {
+            Self::Type(name.to_string(), None)
+        } else {
+            Self::name(name)
+        }
+    }
+
+    /// Read a name that binds whatever it's matched against, even if it's also the name of a type.
+    pub(crate) fn name(name: &str) -> Self {
+        if name == "_" {
+            Self::Wildcard
+        } else if let Some(rest) = name.strip_prefix("...") {
+            Self::Rest(if rest.is_empty() { None } else { Some(rest.to_string()) })
+        } else {
+            Self::Bind(name.to_string())
+        }
+    }
+
+    pub(crate) fn typed(name: &str, tag: &str) -> Result<Self, &'static str> {
+        if TYPES.contains(&tag) {
+            Ok(Self::Type(tag.to_string(), Some(name.to_string())))
+        } else {
+            Err("unknown type in pattern, expected one of int, float, bool, str, path, size, list, table or err")
+        }
+    }
+
+    pub(crate) fn list(mut items: Vec<Self>) -> Result<Self, &'static str> {
+        let rest = match items.last() {
+            Some(Self::Rest(name)) => {
+                let rest = match name {
+                    Some(name) => Self::Bind(name.clone()),
+                    None => Self::Wildcard
+                };
+                items.pop();
+                Some(Box::new(rest))
+            }
+            _ => None
+        };
+
+        for item in &items {
+            item.check()?;
+        }
+        Ok(Self::List(items, rest))
+    }
+
+    pub(crate) fn table(items: BTreeMap<String, Self>) -> Result<Self, &'static str> {
+        for item in items.values() {
+            item.check()?;
+        }
+        Ok(Self::Table(items))
+    }
+
+    /// Make sure that a rest pattern isn't used outside of the end of a list.
+    pub(crate) fn check(&self) -> Result<(), &'static str> {
+        match self {
+            Self::Rest(_) => Err("`...` can only be used at the end of a list pattern"),
+            _ => Ok(())
+        }
+    }
+
+    /// Read a pattern written as a list or table expression, like on the left side of `:=`.
+    /// Every name is bound, so variables can be named `path` or `size` like anywhere else.
+    pub(crate) fn from_value(value: Value) -> Result<Self, &'static str> {
+        match value {
+            Value::Symbol(name) => Ok(Self::name(&name)),
+            Value::List(items) => Self::list(items.into_iter().map(Self::from_value).collect::<Result<_, _>>()?),
+            Value::Table(items) => Self::table(
+                items.into_iter()
+                    .map(|(key, item)| Self::from_value(item).map(|pattern| (key, pattern)))
+                    .collect::<Result<_, _>>()?
+            ),
+            Value::Integer(_) | Value::Float(_) | Value::String(_) | Value::Boolean(_) | Value::Nil => Ok(Self::Literal(value)),
+            _ => Err("invalid pattern, expected a name, a literal, a list or a table")
+        }
+    }
+
+    /// Match a value against this pattern, returning the bindings it makes if it matches.
+    pub fn matches(&self, value: &Value) -> Option<Vec<(String, Value)>> {
+        let mut bindings = vec![];
+        self.bind(value, &mut bindings).ok().map(|_| bindings)
+    }
+
+    /// Get the bindings this pattern makes for a value, or an error explaining why the value doesn't fit.
+    pub fn destructure(&self, value: &Value) -> Result<Vec<(String, Value)>, Error> {
+        let mut bindings = vec![];
+        match self.bind(value, &mut bindings) {
+            Ok(()) => Ok(bindings),
+            Err(reason) => Err(Error::CannotDestructure(value.clone(), reason))
+        }
+    }
+
+    fn bind(&self, value: &Value, bindings: &mut Vec<(String, Value)>) -> Result<(), String> {
+        match (self, value) {
+            (Self::Wildcard, _) => Ok(()),
+            (Self::Bind(name), _) => {
+                bindings.push((name.clone(), value.clone()));
+                Ok(())
+            }
+            (Self::Literal(expected), _) if expected == value => Ok(()),
+            (Self::Literal(expected), _) => Err(format!("expected `{:?}`, found `{:?}`", expected, value)),
+            (Self::Type(tag, name), _) => {
+                let matches = matches!(
+                    (tag.as_str(), value),
+                    ("int", Value::Integer(_))
+                    | ("float", Value::Float(_))
+                    | ("bool", Value::Boolean(_))
+                    | ("str", Value::String(_))
+                    | ("path", Value::Path(_))
+                    | ("size", Value::Size(_))
+                    | ("list", Value::List(_))
+                    | ("table", Value::Table(_))
+                    | ("err", Value::Error(_))
+                );
+                if !matches {
+                    return Err(format!("expected {}, found `{:?}`", tag, value))
+                }
+                if let Some(name) = name {
+                    bindings.push((name.clone(), value.clone()));
+                }
+                Ok(())
+            }
+            (Self::List(patterns, rest), Value::List(items)) => {
+                match rest {
+                    Some(_) if items.len() < patterns.len() => return Err(format!("expected at least {} items, found {}", patterns.len(), items.len())),
+                    None if items.len() != patterns.len() => return Err(format!("expected {} items, found {}", patterns.len(), items.len())),
+                    _ => {}
+                }
+
+                for (pattern, item) in patterns.iter().zip(items) {
+                    pattern.bind(item, bindings)?;
+                }
+                match rest {
+                    Some(rest) => rest.bind(&Value::List(items[patterns.len()..].to_vec()), bindings),
+                    None => Ok(())
+                }
+            }
+            (Self::Table(patterns), Value::Table(items)) => {
+                for (key, pattern) in patterns {
+                    match items.get(key) {
+                        Some(item) => pattern.bind(item, bindings)?,
+                        None => return Err(format!("missing key {:?}", key))
+                    }
+                }
+                Ok(())
+            }
+            (Self::List(..), _) => Err(format!("expected a list, found `{:?}`", value)),
+            (Self::Table(..), _) => Err(format!("expected a table, found `{:?}`", value)),
+            (Self::Rest(_), _) => Err(String::from("`...` can only be used at the end of a list pattern"))
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Self::Wildcard => write!(f, "_"),
+            Self::Bind(name) => write!(f, "{}", name),
+            Self::Literal(value) => write!(f, "{:?}", value),
+            Self::Type(tag, None) => write!(f, "{}", tag),
+            Self::Type(tag, Some(name)) => write!(f, "{}: {}", name, tag),
+            Self::List(items, rest) => {
+                let mut result = items.iter().map(ToString::to_string).collect::<Vec<String>>();
+                match rest.as_deref() {
+                    Some(Self::Bind(name)) => result.push(format!("...{}", name)),
+                    Some(_) => result.push(String::from("...")),
+                    None => {}
+                }
+                write!(f, "[{}]", result.join(", "))
+            }
+            Self::Table(items) => {
+                let result = items.iter().map(|(key, pattern)| format!("{:?}: {}", key, pattern)).collect::<Vec<String>>();
+                write!(f, "{{{}}}", result.join(", "))
+            }
+            Self::Rest(None) => write!(f, "..."),
+            Self::Rest(Some(name)) => write!(f, "...{}", name),
+        }
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pattern, Value, Error};
+    use std::collections::BTreeMap;
+
+    fn symbol(name: &str) -> Value {
+        Value::Symbol(name.to_string())
+    }
+
+    fn reason(pattern: &Pattern, value: &Value) -> String {
+        match pattern.destructure(value) {
+            Err(Error::CannotDestructure(_, reason)) => reason,
+            other => panic!("expected `{}` not to fit, got {:?}", pattern, other.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn lists_bind_their_items_and_the_rest() {
+        let pattern = Pattern::from_value(Value::List(vec![symbol("first"), symbol("_"), symbol("...rest")])).unwrap();
+        let value = Value::List(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3), Value::Integer(4)]);
+        assert_eq!(pattern.destructure(&value).unwrap(), vec![
+            (String::from("first"), Value::Integer(1)),
+            (String::from("rest"), Value::List(vec![Value::Integer(3), Value::Integer(4)])),
+        ]);
+
+        assert_eq!(reason(&pattern, &Value::List(vec![Value::Integer(1)])), "expected at least 2 items, found 1");
+        let exact = Pattern::from_value(Value::List(vec![symbol("a"), symbol("b")])).unwrap();
+        assert_eq!(reason(&exact, &value), "expected 2 items, found 4");
+        assert_eq!(reason(&exact, &Value::Integer(1)), "expected a list, found `1`");
+    }
+
+    #[test]
+    fn rest_patterns_must_end_a_list() {
+        assert!(Pattern::from_value(Value::List(vec![symbol("...rest"), symbol("last")])).is_err());
+        let mut table = BTreeMap::new();
+        table.insert(String::from("a"), symbol("..."));
+        assert!(Pattern::from_value(Value::Table(table)).is_err());
+    }
+
+    #[test]
+    fn tables_need_each_of_their_keys() {
+        let mut keys = BTreeMap::new();
+        keys.insert(String::from("name"), symbol("who"));
+        let pattern = Pattern::from_value(Value::Table(keys)).unwrap();
+
+        let mut value = BTreeMap::new();
+        value.insert(String::from("name"), Value::String(String::from("atom")));
+        value.insert(String::from("other"), Value::Nil);
+        assert_eq!(pattern.destructure(&Value::Table(value)).unwrap(), vec![(String::from("who"), Value::String(String::from("atom")))]);
+        assert_eq!(reason(&pattern, &Value::Table(BTreeMap::new())), "missing key \"name\"");
+    }
+
+    #[test]
+    fn types_and_literals_only_match_some_values() {
+        let pattern = Pattern::typed("n", "int").unwrap();
+        assert_eq!(pattern.matches(&Value::Integer(5)), Some(vec![(String::from("n"), Value::Integer(5))]));
+        assert_eq!(pattern.matches(&Value::Float(5.0)), None);
+        assert_eq!(Pattern::symbol("str").matches(&Value::String(String::from("x"))), Some(vec![]));
+        assert!(Pattern::typed("n", "number").is_err());
+
+        let literal = Pattern::from_value(Value::Integer(1)).unwrap();
+        assert_eq!(literal.matches(&Value::Integer(1)), Some(vec![]));
+        assert_eq!(reason(&literal, &Value::Integer(2)), "expected `1`, found `2`");
+    }
+
+    #[test]
+    fn destructuring_binds_the_names_of_types() {
+        let pattern = Pattern::from_value(Value::List(vec![symbol("size"), symbol("path")])).unwrap();
+        let value = Value::List(vec![Value::Integer(1), Value::String(String::from("a"))]);
+        assert_eq!(pattern.destructure(&value).unwrap(), vec![
+            (String::from("size"), Value::Integer(1)),
+            (String::from("path"), Value::String(String::from("a"))),
+        ]);
+    }
+}
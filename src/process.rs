@@ -0,0 +1,854 @@
+use std::{
+    collections::BTreeMap,
+    env::{split_paths, var_os},
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf, MAIN_SEPARATOR},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::{Mutex, atomic::{AtomicBool, Ordering}},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::{Environment, Error, Value, GLOB};
+use glob::{glob_with, MatchOptions, Pattern};
+use lazy_static::lazy_static;
+
+/// Create a command for a program and its already evaluated arguments,
+/// running in the environment's current working directory with any variables set by `with-env`.
+pub(crate) fn command(program: &Value, args: &[String], env: &Environment) -> Result<Command, Error> {
+    let mut result = Command::new(program.to_string());
+    result.current_dir(env.get_cwd()?);
+    result.args(args);
+    let (set, unset): (Vec<_>, Vec<_>) = env.get_env_vars().into_iter().partition(|(_, value)| value.is_some());
+    result.envs(set.into_iter().filter_map(|(name, value)| Some((name, value?))));
+    for (name, _) in unset {
+        result.env_remove(name);
+    }
+    Ok(result)
+}
+
+/// Find the executable that a program name refers to, searching the directories in `PATH`.
+/// Names containing a path separator are resolved relative to the current working directory instead.
+pub(crate) fn which(name: &str, env: &Environment) -> Result<Option<PathBuf>, Error> {
+    if name.is_empty() {
+        return Ok(None)
+    }
+
+    let cwd = env.get_cwd()?;
+    if Path::new(name).components().count() > 1 {
+        let path = cwd.join(name).components().filter(|part| *part != Component::CurDir).collect::<PathBuf>();
+        return Ok(executable(&path))
+    }
+
+    // Respect a `PATH` set by `with-env`, since that's the one the program would be run with
+    let path = match env.get_env_vars().remove("PATH") {
+        Some(path) => path.map(OsString::from),
+        None => var_os("PATH")
+    };
+
+    Ok(match path {
+        Some(path) => split_paths(&path).find_map(|dir| executable(&cwd.join(dir).join(name))),
+        None => None
+    })
+}
+
+/// Get the path to an executable file, trying each extension in `PATHEXT` on Windows.
+fn executable(path: &Path) -> Option<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        match path.metadata() {
+            Ok(metadata) if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0 => Some(path.to_path_buf()),
+            _ => None
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        if path.is_file() {
+            return Some(path.to_path_buf())
+        }
+        let extensions = var_os("PATHEXT").unwrap_or_else(|| OsString::from(".COM;.EXE;.BAT;.CMD"));
+        extensions.to_string_lossy().split(';')
+            .map(|extension| path.with_extension(extension.trim_start_matches('.')))
+            .find(|path| path.is_file())
+    }
+}
+
+/// The command that a program and its arguments would be written as.
+pub(crate) fn describe(program: Value, args: Vec<String>) -> Value {
+    Value::Run(Box::new(program), args.into_iter().map(Value::String).collect())
+}
+
+/// Find the paths matching a glob pattern, relative to the current working directory.
+/// Wildcards don't match a leading `.`, so hidden files are only matched by patterns that spell it out.
+pub(crate) fn glob(pattern: &str, env: &Environment) -> Result<Vec<PathBuf>, Error> {
+    let cwd = env.get_cwd()?;
+    let full_pattern = if Path::new(pattern).is_absolute() {
+        pattern.to_string()
+    } else {
+        format!("{}/{}", Pattern::escape(&cwd.to_string_lossy()), pattern)
+    };
+
+    let options = MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    let (paths, compiled) = match (glob_with(&full_pattern, options), Pattern::new(&full_pattern)) {
+        (Ok(paths), Ok(compiled)) => (paths, compiled),
+        (Err(e), _) | (_, Err(e)) => return Err(Error::CustomError(format!("invalid glob pattern {:?}: {}", pattern, e.msg)))
+    };
+
+    // Searching directories with `require_literal_leading_dot` skips every hidden file,
+    // even when the pattern spells out the dot, so hidden files are filtered out afterwards instead
+    let hidden = MatchOptions { require_literal_leading_dot: true, ..options };
+    Ok(paths.filter_map(Result::ok)
+        .filter(|path| compiled.matches_path_with(path, hidden))
+        .filter(|path| {
+            let path = path.to_string_lossy();
+            let name = path.rsplit(['/', MAIN_SEPARATOR]).next();
+            name != Some(".") && name != Some("..")
+        })
+        .map(|path| match path.strip_prefix(&cwd) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => path
+        })
+        .collect())
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Add an evaluated argument to the strings passed to a program.
+/// A list is passed as one argument per element, rather than as its printed form,
+/// so that a list of paths or a macro's rest parameter can be forwarded to a program.
+fn push_arg(value: Value, result: &mut Vec<String>) {
+    match value {
+        Value::List(items) => result.extend(items.iter().map(ToString::to_string)),
+        value => result.push(value.to_string())
+    }
+}
+
+/// Evaluate the arguments to a program into the strings passed to it.
+/// When `GLOB` is enabled, unquoted symbols containing wildcards are replaced with the paths they match.
+pub(crate) fn eval_args(args: &[Value], env: &mut Environment) -> Result<Vec<String>, Error> {
+    let expand = env.get(GLOB)? == Value::Boolean(true);
+    let mut result = vec![];
+    for arg in args {
+        match arg {
+            // Like other shells, a pattern that matches nothing is passed as it is
+            Value::Symbol(pattern) if expand && is_glob(pattern) && !env.is_defined(pattern) => {
+                let paths = glob(pattern, env)?;
+                if paths.is_empty() {
+                    result.push(pattern.clone());
+                } else {
+                    result.extend(paths.into_iter().map(|path| Value::Path(path).to_string()));
+                }
+            }
+            _ => push_arg(arg.eval(env)?, &mut result)
+        }
+    }
+    Ok(result)
+}
+
+/// A command in a pipeline, along with the files its standard streams are redirected to.
+pub(crate) struct Stage {
+    pub(crate) command: Command,
+    pub(crate) stdin: Option<File>,
+    pub(crate) stdout: Option<File>,
+    pub(crate) merge_stderr: bool,
+    /// Data written to the command's standard input, instead of a file or pipe
+    pub(crate) input: Option<Vec<u8>>,
+}
+
+impl Stage {
+    pub(crate) fn new(command: Command) -> Self {
+        Self { command, stdin: None, stdout: None, merge_stderr: false, input: None }
+    }
+
+    /// The program this stage runs, and its arguments.
+    pub(crate) fn program(&self) -> (Value, Vec<String>) {
+        let program = Value::string(self.command.get_program().to_string_lossy());
+        let args = self.command.get_args().map(|arg| arg.to_string_lossy().to_string()).collect();
+        (program, args)
+    }
+}
+
+/// Get the data written to a program's standard input for a value.
+/// Strings are written verbatim, and lists are written one element per line.
+pub(crate) fn input(value: &Value) -> Vec<u8> {
+    match value {
+        Value::List(items) => items.iter().map(|item| format!("{}\n", item)).collect::<String>().into_bytes(),
+        other => other.to_string().into_bytes(),
+    }
+}
+
+/// Write data to a child's standard input on another thread, so that
+/// the child can't deadlock by filling its output pipe while we're writing.
+fn feed(child: &mut Child, input: Vec<u8>) -> Option<JoinHandle<()>> {
+    let mut stdin = child.stdin.take()?;
+    Some(thread::spawn(move || {
+        // The child may exit without reading all of its input
+        let _ = stdin.write_all(&input);
+    }))
+}
+
+/// Read everything from a child's output stream on another thread.
+fn collect(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut result = vec![];
+        let _ = pipe.read_to_end(&mut result);
+        result
+    })
+}
+
+/// How often a child with a time limit is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long a child that timed out has to exit after being asked to terminate, before it's killed.
+const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Get the time limit a value describes in seconds, where nil means there's no limit.
+pub(crate) fn timeout(value: &Value) -> Result<Option<Duration>, Error> {
+    match value {
+        Value::Nil => Ok(None),
+        Value::Integer(n) if *n >= 0 => Ok(Some(Duration::from_secs(*n as u64))),
+        Value::Float(n) if *n >= 0.0 && n.is_finite() => Ok(Some(Duration::from_secs_f64(*n))),
+        other => Err(Error::CustomError(format!("invalid timeout {:?}", other)))
+    }
+}
+
+/// Wait for a child to finish, giving up if it's still running at the deadline.
+fn wait_until(child: &mut Child, deadline: Option<Instant>) -> io::Result<Option<ExitStatus>> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return child.wait().map(Some)
+    };
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status))
+        } else if Instant::now() >= deadline {
+            return Ok(None)
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Put a command with a time limit in a process group, so that the programs it starts can be stopped along with it.
+/// A group of `0` starts a new group led by the command.
+#[cfg(unix)]
+fn join_group(cmd: &mut Command, group: u32) {
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(group as i32);
+}
+
+#[cfg(not(unix))]
+fn join_group(_: &mut Command, _: u32) {}
+
+/// Stop children that ran for too long, asking them to terminate before killing them.
+/// The first child leads the process group the others were started in, so everything they started is stopped too.
+fn terminate(children: &mut [Child]) {
+    #[cfg(unix)]
+    if let Some(leader) = children.first() {
+        let group = -(leader.id() as libc::pid_t);
+        unsafe { libc::kill(group, libc::SIGTERM); }
+        let deadline = Instant::now() + TERMINATE_GRACE_PERIOD;
+        for child in children.iter_mut() {
+            let _ = wait_until(child, Some(deadline));
+        }
+        // Programs started by the children can outlive them, and keep the group around
+        while unsafe { libc::kill(group, 0) } == 0 && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+        if unsafe { libc::kill(group, 0) } == 0 {
+            unsafe { libc::kill(group, libc::SIGKILL); }
+        }
+    }
+
+    for child in children {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+fn timed_out_error() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "program timed out")
+}
+
+/// The error for a command that was terminated because it ran for longer than its timeout.
+pub(crate) fn timed_out(command: Value, timeout: Option<Duration>) -> Error {
+    Error::CommandTimedOut(command, timeout.unwrap_or_default().as_secs_f64())
+}
+
+/// Run a command to completion, terminating it if it runs for longer than the timeout.
+pub(crate) fn status(cmd: &mut Command, timeout: Option<Duration>) -> io::Result<ExitStatus> {
+    if timeout.is_some() {
+        join_group(cmd, 0);
+    }
+    let mut child = cmd.spawn()?;
+    let _foreground = Foreground::new(&child, timeout.is_some());
+    match wait_until(&mut child, timeout.map(|timeout| Instant::now() + timeout))? {
+        Some(status) => Ok(status),
+        None => {
+            terminate(std::slice::from_mut(&mut child));
+            Err(timed_out_error())
+        }
+    }
+}
+
+/// Run a command to completion, optionally writing data to its standard input.
+/// Only the output streams the command was configured to pipe are captured.
+/// If the command runs for longer than the timeout, it's terminated and an error of kind `TimedOut` is returned.
+pub(crate) fn output(cmd: &mut Command, input: Option<Vec<u8>>, timeout: Option<Duration>) -> io::Result<Output> {
+    if input.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    if timeout.is_some() {
+        join_group(cmd, 0);
+    }
+    let mut child = cmd.spawn()?;
+    let _foreground = Foreground::new(&child, timeout.is_some());
+    let writer = input.and_then(|input| feed(&mut child, input));
+    let stdout = child.stdout.take().map(collect);
+    let stderr = child.stderr.take().map(collect);
+
+    match wait_until(&mut child, timeout.map(|timeout| Instant::now() + timeout))? {
+        Some(status) => {
+            if let Some(writer) = writer {
+                let _ = writer.join();
+            }
+            let stdout = stdout.and_then(|reader| reader.join().ok()).unwrap_or_default();
+            let stderr = stderr.and_then(|reader| reader.join().ok()).unwrap_or_default();
+            Ok(Output { status, stdout, stderr })
+        }
+        None => {
+            terminate(std::slice::from_mut(&mut child));
+            // Once the program's whole process group is gone, nothing holds its pipes open anymore.
+            // Elsewhere, the programs it started may still be running, so the threads are left to finish on their own
+            if cfg!(unix) {
+                for reader in stdout.into_iter().chain(stderr) {
+                    let _ = reader.join();
+                }
+                if let Some(writer) = writer {
+                    let _ = writer.join();
+                }
+            }
+            Err(timed_out_error())
+        }
+    }
+}
+
+/// Open the file a program's standard stream is redirected to, relative to the current working directory.
+pub(crate) fn open(path: &Value, options: &OpenOptions, env: &Environment) -> Result<File, Error> {
+    match options.open(env.get_cwd()?.join(path.to_string())) {
+        Ok(file) => Ok(file),
+        Err(_) => Err(Error::CustomError(format!("could not open file {:?}", path.to_string())))
+    }
+}
+
+/// The error for a command that couldn't be started.
+/// A program that doesn't exist is reported separately, so that the `command-not-found` hook can handle it.
+pub(crate) fn spawn_failed(command: &Command, error: io::Error) -> Error {
+    let program = Value::string(command.get_program().to_string_lossy());
+    if error.kind() == io::ErrorKind::NotFound {
+        Error::ProgramNotFound(program, command.get_args().map(|arg| Value::string(arg.to_string_lossy())).collect())
+    } else {
+        Error::CannotExecuteProgram(program)
+    }
+}
+
+fn pipe_error(_: io::Error) -> Error {
+    Error::CustomError(String::from("could not create pipe"))
+}
+
+/// Run a pipeline of commands, connecting the standard output of each command
+/// to the standard input of the next. This returns the exit code of every command,
+/// or `None` if the pipeline ran for longer than the timeout and was terminated.
+pub(crate) fn pipeline(stages: Vec<Stage>, timeout: Option<Duration>) -> Result<Option<Vec<i32>>, Error> {
+    let mut children = vec![];
+    let mut foreground = vec![];
+    let mut writers = vec![];
+    let mut spawn_error = None;
+    let mut previous: Option<Stdio> = None;
+    let count = stages.len();
+
+    for (i, mut stage) in stages.into_iter().enumerate() {
+        match (stage.stdin.take(), previous.take()) {
+            (Some(file), _) => { stage.command.stdin(file); }
+            (None, Some(input)) => { stage.command.stdin(input); }
+            (None, None) => {}
+        }
+        if stage.input.is_some() {
+            stage.command.stdin(Stdio::piped());
+        }
+
+        if let Some(file) = stage.stdout.take() {
+            if stage.merge_stderr {
+                stage.command.stderr(file.try_clone().map_err(pipe_error)?);
+            }
+            stage.command.stdout(file);
+            // Nothing is written to the next command if this one writes to a file
+            previous = Some(Stdio::null());
+        } else if i + 1 < count {
+            let (reader, writer) = io::pipe().map_err(pipe_error)?;
+            if stage.merge_stderr {
+                stage.command.stderr(writer.try_clone().map_err(pipe_error)?);
+            }
+            stage.command.stdout(writer);
+            previous = Some(Stdio::from(reader));
+        } else if stage.merge_stderr {
+            stage.command.stderr(io::stdout());
+        }
+
+        // With a time limit, the whole pipeline is put in the first command's process group
+        if timeout.is_some() {
+            join_group(&mut stage.command, children.first().map_or(0, Child::id));
+        }
+
+        // The command is dropped after spawning so that our copies of its pipes are closed
+        match stage.command.spawn() {
+            Ok(mut child) => {
+                if let Some(input) = stage.input.take() {
+                    writers.extend(feed(&mut child, input));
+                }
+                if timeout.is_none() || children.is_empty() {
+                    foreground.push(Foreground::new(&child, timeout.is_some()));
+                }
+                children.push(child)
+            }
+            Err(e) => {
+                spawn_error = Some(spawn_failed(&stage.command, e));
+                break
+            }
+        }
+    }
+    drop(previous);
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut codes = vec![];
+    let mut expired = false;
+    for child in &mut children {
+        codes.push(match wait_until(child, deadline) {
+            Ok(Some(status)) => exit_code(&status),
+            Ok(None) => {
+                expired = true;
+                break
+            }
+            Err(_) => 1
+        });
+    }
+    if expired {
+        terminate(&mut children);
+    }
+    for writer in writers {
+        let _ = writer.join();
+    }
+    check_interrupt()?;
+
+    match spawn_error {
+        Some(e) => Err(e),
+        None if expired => Ok(None),
+        None => Ok(Some(codes))
+    }
+}
+
+/// Get the integer exit code of a finished program.
+/// Programs killed without an exit code are treated as exiting with `1`.
+pub(crate) fn exit_code(status: &ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+/// Get the signal that killed a program, if it was killed by one.
+#[cfg(unix)]
+pub(crate) fn signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn signal(_: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// The error for a program that exited with a non-zero code.
+pub(crate) fn failed(program: &Value, args: &[String], code: i32) -> Error {
+    Error::ProgramFailed(program.clone(), args.iter().map(Value::string).collect(), code)
+}
+
+/// A program running in the background.
+struct Job {
+    command: String,
+    child: Child,
+    status: Option<ExitStatus>,
+}
+
+impl Job {
+    /// Check whether the job has finished, without blocking.
+    fn poll(&mut self) -> Option<ExitStatus> {
+        if self.status.is_none() {
+            if let Ok(status) = self.child.try_wait() {
+                self.status = status;
+            }
+        }
+        self.status
+    }
+
+    fn describe(&mut self, id: usize) -> String {
+        let state = match self.poll() {
+            None => String::from("running"),
+            Some(status) => match signal(&status) {
+                Some(signal) => format!("killed by signal {}", signal),
+                None => format!("done ({})", exit_code(&status)),
+            }
+        };
+        format!("[{}] {:<20} {}", id, state, self.command)
+    }
+}
+
+struct Jobs {
+    next: usize,
+    running: BTreeMap<usize, Job>,
+}
+
+lazy_static! {
+    static ref JOBS: Mutex<Jobs> = Mutex::new(Jobs { next: 1, running: BTreeMap::new() });
+}
+
+/// Start a command in the background, and return its job number.
+pub(crate) fn background(mut stage: Stage, command: String) -> Result<usize, Error> {
+    // Background jobs don't read from the terminal
+    match (stage.stdin.take(), &stage.input) {
+        (Some(file), _) => { stage.command.stdin(file); }
+        (None, Some(_)) => { stage.command.stdin(Stdio::piped()); }
+        (None, None) => { stage.command.stdin(Stdio::null()); }
+    }
+
+    if let Some(file) = stage.stdout.take() {
+        if stage.merge_stderr {
+            stage.command.stderr(file.try_clone().map_err(pipe_error)?);
+        }
+        stage.command.stdout(file);
+    } else if stage.merge_stderr {
+        stage.command.stderr(io::stdout());
+    }
+
+    // Keep terminal signals such as CTRL+C meant for the foreground from reaching the job
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        stage.command.process_group(0);
+    }
+
+    let mut child = match stage.command.spawn() {
+        Ok(child) => child,
+        Err(e) => return Err(spawn_failed(&stage.command, e))
+    };
+    if let Some(input) = stage.input.take() {
+        feed(&mut child, input);
+    }
+
+    let mut jobs = JOBS.lock().unwrap();
+    let id = jobs.next;
+    jobs.next += 1;
+    jobs.running.insert(id, Job { command, child, status: None });
+    Ok(id)
+}
+
+/// Describe every job, forgetting the jobs that have finished.
+pub(crate) fn list_jobs() -> Vec<String> {
+    let mut jobs = JOBS.lock().unwrap();
+    let mut result = vec![];
+    for (id, job) in jobs.running.iter_mut() {
+        result.push(job.describe(*id));
+    }
+    jobs.running.retain(|_, job| job.status.is_none());
+    result
+}
+
+/// Describe the jobs that finished since this was last called.
+/// Finished jobs are kept until they're waited on or listed, so their status can still be retrieved.
+pub fn finished_jobs() -> Vec<String> {
+    let mut jobs = JOBS.lock().unwrap();
+    let mut result = vec![];
+    for (id, job) in jobs.running.iter_mut() {
+        if job.status.is_none() && job.poll().is_some() {
+            result.push(job.describe(*id));
+        }
+    }
+    result
+}
+
+/// Wait for a job to finish, and return its exit code.
+/// A job brought to the foreground is interrupted along with the shell,
+/// while a job that's only waited on keeps running when the wait is interrupted.
+/// Jobs never get the terminal back, since their input was already closed when they were started.
+pub(crate) fn wait_job(id: usize, foreground: bool) -> Result<i32, Error> {
+    // The job is removed from the table while waiting, so other jobs can still be managed
+    let mut job = match JOBS.lock().unwrap().running.remove(&id) {
+        Some(job) => job,
+        None => return Err(Error::JobNotFound(id))
+    };
+    if let Some(status) = job.status {
+        return Ok(exit_code(&status))
+    }
+
+    let _foreground = if foreground { Some(Foreground::new(&job.child, true)) } else { None };
+    loop {
+        match job.child.try_wait() {
+            Ok(Some(status)) => return Ok(exit_code(&status)),
+            Ok(None) => {}
+            Err(_) => return Err(Error::JobNotFound(id))
+        }
+        if let Err(e) = check_interrupt() {
+            // The job is put back, in case it's still running
+            JOBS.lock().unwrap().running.insert(id, job);
+            return Err(e)
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The IDs of every job.
+pub(crate) fn job_ids() -> Vec<usize> {
+    JOBS.lock().unwrap().running.keys().copied().collect()
+}
+
+/// Send a signal to a job.
+pub(crate) fn signal_job(id: usize, signal: i32) -> Result<(), Error> {
+    let mut jobs = JOBS.lock().unwrap();
+    match jobs.running.get_mut(&id) {
+        Some(job) => match job.poll() {
+            None => send_signal(&mut job.child, signal),
+            Some(_) => Err(Error::JobNotFound(id))
+        },
+        None => Err(Error::JobNotFound(id))
+    }
+}
+
+#[cfg(unix)]
+fn send_signal(child: &mut Child, signal: i32) -> Result<(), Error> {
+    // Jobs lead their own process group, so the whole group is signaled
+    if unsafe { libc::kill(-(child.id() as libc::pid_t), signal) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::CustomError(format!("could not send signal {} to process {}", signal, child.id())))
+    }
+}
+
+#[cfg(not(unix))]
+fn send_signal(child: &mut Child, signal: i32) -> Result<(), Error> {
+    // Without signals, the only thing we can do is terminate the process
+    match signal {
+        2 | 9 | 15 => match child.kill() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::CustomError(format!("could not kill process {}", child.id())))
+        },
+        _ => Err(Error::CustomError(format!("signal {} is not supported on this platform", signal)))
+    }
+}
+
+/// Get a signal number from its name, such as `"TERM"` or `"SIGKILL"`.
+#[cfg(unix)]
+pub(crate) fn signal_number(name: &str) -> Option<i32> {
+    let name = name.to_uppercase();
+    Some(match name.trim_start_matches("SIG") {
+        "HUP"  => libc::SIGHUP,
+        "INT"  => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "TERM" => libc::SIGTERM,
+        "STOP" => libc::SIGSTOP,
+        "CONT" => libc::SIGCONT,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        _ => return None
+    })
+}
+
+#[cfg(not(unix))]
+pub(crate) fn signal_number(name: &str) -> Option<i32> {
+    let name = name.to_uppercase();
+    match name.trim_start_matches("SIG") {
+        "INT" => Some(2),
+        "KILL" => Some(9),
+        "TERM" => Some(15),
+        _ => None
+    }
+}
+
+/// The signal used to resume a stopped job.
+#[cfg(unix)]
+pub(crate) const SIGCONT: i32 = libc::SIGCONT;
+#[cfg(not(unix))]
+pub(crate) const SIGCONT: i32 = 18;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// The programs the shell is currently waiting on, which are interrupted along with it,
+    /// and whether each of them leads its own process group.
+    static ref FOREGROUND: Mutex<Vec<(u32, bool)>> = Mutex::new(vec![]);
+}
+
+/// Marks a child as running in the foreground until this is dropped.
+struct Foreground(u32);
+
+impl Foreground {
+    /// Mark a child as running in the foreground. A child that leads its own process group
+    /// doesn't receive CTRL+C from the terminal, so it's sent to the whole group instead.
+    fn new(child: &Child, group: bool) -> Self {
+        FOREGROUND.lock().unwrap().push((child.id(), group));
+        Self(child.id())
+    }
+}
+
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        FOREGROUND.lock().unwrap().retain(|(pid, _)| *pid != self.0);
+    }
+}
+
+/// Interrupt the evaluation that's currently running, and the programs it's waiting on.
+/// Evaluation stops with an `Interrupted` error at the next loop iteration, statement, or function call.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    for (pid, group) in FOREGROUND.lock().unwrap().iter() {
+        send_interrupt(*pid, *group);
+    }
+}
+
+#[cfg(unix)]
+fn send_interrupt(pid: u32, group: bool) {
+    let pid = pid as libc::pid_t;
+    if group {
+        unsafe { libc::kill(-pid, libc::SIGINT); }
+    } else if !in_terminal_foreground() {
+        // Otherwise the child is in our process group, and CTRL+C from the terminal already reached it.
+        // Sending it again would make programs that quit on a second CTRL+C skip cleaning up
+        unsafe { libc::kill(pid, libc::SIGINT); }
+    }
+}
+
+/// Whether the shell's process group is the terminal's foreground group, which receives CTRL+C from the terminal.
+#[cfg(unix)]
+fn in_terminal_foreground() -> bool {
+    let group = unsafe { libc::getpgrp() };
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO].iter()
+        .any(|fd| unsafe { libc::tcgetpgrp(*fd) } == group)
+}
+
+#[cfg(not(unix))]
+fn send_interrupt(_: u32, _: bool) {
+    // Console programs on Windows receive CTRL+C along with the shell
+}
+
+/// Forget an interrupt, once evaluation has stopped because of it.
+pub fn clear_interrupt() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Stop evaluating if the shell has been interrupted.
+pub(crate) fn check_interrupt() -> Result<(), Error> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        Err(Error::Interrupted)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+
+    fn command(program: &str, args: &[&str]) -> Command {
+        let mut result = Command::new(program);
+        result.args(args);
+        result
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("atom-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn lists_are_passed_as_one_argument_per_element() {
+        let mut env = Environment::new();
+        let args = vec![Value::string("-f"), Value::List(vec![Value::string("a b"), Value::Integer(1)]), Value::symbol("*.o")];
+        // Without `GLOB`, patterns are passed as they are
+        assert_eq!(eval_args(&args, &mut env).unwrap(), vec!["-f", "a b", "1", "*.o"]);
+    }
+
+    #[test]
+    fn pipeline_connects_each_command_to_the_next() {
+        let path = temp_file("pipeline");
+        let mut last = Stage::new(command("wc", &["-l"]));
+        last.stdout = Some(File::create(&path).unwrap());
+        let codes = pipeline(vec![Stage::new(command("printf", &["a\\nb\\nc\\n"])), last], None).unwrap();
+
+        assert_eq!(codes, Some(vec![0, 0]));
+        assert_eq!(read_to_string(&path).unwrap().trim(), "3");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn pipeline_reports_every_exit_code() {
+        let codes = pipeline(vec![Stage::new(command("false", &[])), Stage::new(command("true", &[]))], None).unwrap();
+        assert_eq!(codes, Some(vec![1, 0]));
+    }
+
+    #[test]
+    fn redirections_and_input_are_applied() {
+        let path = temp_file("redirect");
+        let mut stage = Stage::new(command("tr", &["a-z", "A-Z"]));
+        stage.input = Some(input(&Value::List(vec![Value::string("hello"), Value::string("world")])));
+        stage.stdout = Some(File::create(&path).unwrap());
+        assert_eq!(pipeline(vec![stage], None).unwrap(), Some(vec![0]));
+        assert_eq!(read_to_string(&path).unwrap(), "HELLO\nWORLD\n");
+
+        let mut stage = Stage::new(command("grep", &["-c", "O"]));
+        stage.stdin = Some(File::open(&path).unwrap());
+        let output = output(stage.command.stdin(stage.stdin.take().unwrap()).stdout(Stdio::piped()), None, None).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn missing_programs_are_not_found() {
+        match pipeline(vec![Stage::new(command("atom-missing-program", &["x"]))], None) {
+            Err(Error::ProgramNotFound(program, args)) => {
+                assert_eq!(program, Value::string("atom-missing-program"));
+                assert_eq!(args, vec![Value::string("x")]);
+            }
+            other => panic!("expected a missing program, found {:?}", other)
+        }
+    }
+
+    #[test]
+    fn timeouts_stop_the_programs_a_command_started() {
+        let start = Instant::now();
+        let timeout = Some(Duration::from_millis(200));
+        // The shell's child holds the output pipe, so this only finishes once it's stopped too
+        let result = output(command("sh", &["-c", "sleep 10; echo done"]).stdout(Stdio::piped()), None, timeout);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let start = Instant::now();
+        let result = status(&mut command("sh", &["-c", "sleep 10"]), timeout);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn pipelines_that_time_out_are_stopped() {
+        let start = Instant::now();
+        let stages = vec![Stage::new(command("sh", &["-c", "sleep 10"])), Stage::new(command("cat", &[]))];
+        assert_eq!(pipeline(stages, Some(Duration::from_millis(200))).unwrap(), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
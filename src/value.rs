@@ -1,11 +1,13 @@
 use std::{
     collections::BTreeMap,
-    process::Command,
+    fs::OpenOptions,
+    io,
     path::PathBuf,
+    time::Instant,
     fmt,
 };
 
-use super::{Environment, Error};
+use super::{Environment, Error, Pattern, PIPEFAIL, STRICT, COMMAND_NOT_FOUND, LAST_STATUS, LAST_DURATION, LAST_ERROR, process};
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Size {
@@ -37,21 +39,171 @@ impl fmt::Display for Size {
     }
 }
 
+/// Where a program's standard streams are redirected to or from.
+#[derive(Clone, PartialEq)]
+pub enum Redirection {
+    Write(Value),
+    Append(Value),
+    Read(Value),
+    MergeStderr,
+}
+
+impl fmt::Display for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Self::Write(path)  => write!(f, "> {}", path),
+            Self::Append(path) => write!(f, ">> {}", path),
+            Self::Read(path)   => write!(f, "< {}", path),
+            Self::MergeStderr  => write!(f, "2>&1"),
+        }
+    }
+}
+
+impl fmt::Debug for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Self::Write(path)  => write!(f, "> {:?}", path),
+            Self::Append(path) => write!(f, ">> {:?}", path),
+            Self::Read(path)   => write!(f, "< {:?}", path),
+            Self::MergeStderr  => write!(f, "2>&1"),
+        }
+    }
+}
+
+/// A parameter of a function or macro.
+#[derive(Clone)]
+pub struct Param {
+    pub name: String,
+    /// The expression used when no argument is given for this parameter.
+    pub default: Option<Value>,
+    /// Whether this parameter collects the remaining arguments into a list, written as `...name`.
+    pub rest: bool,
+}
+
+impl Param {
+    pub fn new(name: impl ToString) -> Self {
+        let name = name.to_string();
+        match name.strip_prefix("...") {
+            Some(rest) => Self { name: rest.to_string(), default: None, rest: true },
+            None => Self { name, default: None, rest: false }
+        }
+    }
+
+    pub fn with_default(name: impl ToString, default: Value) -> Self {
+        Self { default: Some(default), ..Self::new(name) }
+    }
+
+    /// Check that a parameter list has unique names, and at most one rest parameter at the end.
+    pub(crate) fn list(params: Vec<Self>) -> Result<Vec<Self>, &'static str> {
+        for (i, param) in params.iter().enumerate() {
+            if param.rest && param.default.is_some() {
+                return Err("a rest parameter can't have a default value")
+            }
+            if param.rest && i + 1 != params.len() {
+                return Err("a rest parameter must be the last parameter")
+            }
+            if params[..i].iter().any(|other| other.name == param.name) {
+                return Err("duplicate parameter name")
+            }
+        }
+        Ok(params)
+    }
+
+    /// The value of this parameter in a call, using its default if it wasn't given an argument.
+    fn value(&self, arg: Option<Value>, env: &mut Environment) -> Result<Value, Error> {
+        match (arg, &self.default) {
+            (Some(val), _) => Ok(val),
+            (None, Some(default)) => default.eval(env),
+            (None, None) => Ok(Value::Nil)
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match &self.default {
+            _ if self.rest => write!(f, "...{}", self.name),
+            Some(default) => write!(f, "{} = {:?}", self.name, default),
+            None => write!(f, "{}", self.name)
+        }
+    }
+}
+
+/// Evaluate the arguments of a call, and give each parameter of the function the argument meant for it.
+/// Positional arguments fill the parameters in order, and named arguments fill the parameter with their name.
+/// Parameters that weren't given an argument are left as `None`, to be filled in with their defaults.
+fn match_args(func: &Value, params: &[Param], args: &[Value], env: &mut Environment) -> Result<Vec<Option<Value>>, Error> {
+    let positional = (0..params.len()).filter(|&i| !params[i].rest).collect::<Vec<usize>>();
+    let rest = params.iter().position(|param| param.rest);
+
+    // Work out where every argument goes before evaluating any of them
+    let mut targets = vec![];
+    let mut filled = vec![false; params.len()];
+    let mut next = 0;
+    for arg in args {
+        let target = match arg {
+            Value::Named(name, _) => match params.iter().position(|param| !param.rest && param.name == *name) {
+                Some(i) => i,
+                None => return Err(Error::UnknownParameter(func.clone(), name.clone()))
+            },
+            _ => match (positional.get(next), rest) {
+                (Some(&i), _) => { next += 1; i }
+                (None, Some(i)) => i,
+                (None, None) => return Err(Error::TooManyArguments(func.clone(), args.to_vec()))
+            }
+        };
+
+        if filled[target] && !params[target].rest {
+            return Err(Error::DuplicateArgument(func.clone(), params[target].name.clone()))
+        }
+        filled[target] = true;
+        targets.push(target);
+    }
+
+    if params.iter().zip(&filled).any(|(param, filled)| !filled && !param.rest && param.default.is_none()) {
+        return Err(Error::TooFewArguments(func.clone(), args.to_vec()))
+    }
+
+    let mut result = vec![None; params.len()];
+    let mut extra = vec![];
+    for (arg, target) in args.iter().zip(targets) {
+        let val = match arg {
+            Value::Named(_, val) => val.eval(env)?,
+            arg => arg.eval(env)?
+        };
+        if params[target].rest {
+            extra.push(val);
+        } else {
+            result[target] = Some(val);
+        }
+    }
+    if let Some(i) = rest {
+        result[i] = Some(Value::List(extra));
+    }
+    Ok(result)
+}
+
 #[derive(Clone)]
 pub enum Value {
     Symbol(String),
 
     Builtin(String, fn(&Vec<Value>, &mut Environment) -> Result<Value, Error>),
     Apply(Box<Self>, Vec<Self>),
+    Named(String, Box<Self>),
     
     Index(Box<Self>, Box<Self>),
+    Interpolate(Vec<Self>),
 
     Run(Box<Self>, Vec<Self>),
-    Macro(Vec<String>, Box<Self>),
-    Lambda(Vec<String>, Box<Self>, Environment),
+    Redirect(Box<Self>, Vec<Redirection>),
+    Pipe(Vec<Self>),
+    Background(Box<Self>),
+    Macro(Vec<Param>, Box<Self>),
+    Lambda(Vec<Param>, Box<Self>, Environment),
 
     Range(Box<Self>, Box<Self>),
     Define(String, Box<Self>),
+    Destructure(Box<Pattern>, Box<Self>),
     
     // For AST purposes
     Grouped(Box<Self>),
@@ -80,10 +232,13 @@ pub enum Value {
     Conditional(Box<Self>, Box<Self>, Box<Self>),
     If(Box<Self>, Box<Self>, Box<Self>),
     While(Box<Self>, Box<Self>),
-    For(String, Box<Self>, Box<Self>),
+    For(Box<Pattern>, Box<Self>, Box<Self>),
+    Match(Box<Self>, Vec<(Pattern, Option<Self>, Self)>),
+    Try(Box<Self>, String, Box<Self>),
 
     Path(PathBuf),
     Size(Size),
+    Job(usize),
 
     Boolean(bool),
     String(String),
@@ -109,6 +264,106 @@ impl Value {
         Self::Builtin(name.to_string(), body)
     }
 
+    /// Render the syntax tree of a parsed value, one node per line,
+    /// with each node's children indented beneath it.
+    pub fn ast(&self) -> String {
+        let mut result = String::new();
+        self.write_ast(0, &mut result);
+        result
+    }
+
+    fn write_ast(&self, depth: usize, out: &mut String) {
+        let (label, children): (String, Vec<&Self>) = match self {
+            Self::Symbol(name) => (format!("Symbol {}", name), vec![]),
+            Self::Builtin(name, _) => (format!("Builtin {}", name), vec![]),
+            Self::Apply(f, args) => (String::from("Apply"), std::iter::once(&**f).chain(args).collect()),
+            Self::Index(val, idx) => (String::from("Index"), vec![val, idx]),
+            Self::Named(name, val) => (format!("Named {}", name), vec![val]),
+            Self::Interpolate(parts) => (String::from("Interpolate"), parts.iter().collect()),
+
+            Self::Run(program, args) => (String::from("Run"), std::iter::once(&**program).chain(args).collect()),
+            Self::Redirect(cmd, redirections) => (
+                format!("Redirect {}", redirections.iter().map(|r| format!("{:?}", r)).collect::<Vec<String>>().join(" ")),
+                vec![cmd]
+            ),
+            Self::Pipe(stages) => (String::from("Pipe"), stages.iter().collect()),
+            Self::Background(cmd) => (String::from("Background"), vec![cmd]),
+            Self::Macro(params, body) => (format!("Macro({})", params.iter().map(ToString::to_string).collect::<Vec<String>>().join(", ")), vec![body]),
+            Self::Lambda(params, body, _) => (format!("Lambda({})", params.iter().map(ToString::to_string).collect::<Vec<String>>().join(", ")), vec![body]),
+
+            Self::Range(from, to) => (String::from("Range"), vec![from, to]),
+            Self::Define(name, val) => (format!("Define {}", name), vec![val]),
+            Self::Destructure(pattern, val) => (format!("Destructure {}", pattern), vec![val]),
+            Self::Grouped(inner) => (String::from("Grouped"), vec![inner]),
+
+            Self::Do(exprs) => (String::from("Do"), exprs.iter().collect()),
+            Self::Scope(exprs) => (String::from("Scope"), exprs.iter().collect()),
+
+            Self::And(a, b) => (String::from("And"), vec![a, b]),
+            Self::Or(a, b) => (String::from("Or"), vec![a, b]),
+            Self::Not(a) => (String::from("Not"), vec![a]),
+
+            Self::Negate(a) => (String::from("Negate"), vec![a]),
+            Self::Add(a, b) => (String::from("Add"), vec![a, b]),
+            Self::Multiply(a, b) => (String::from("Multiply"), vec![a, b]),
+            Self::Divide(a, b) => (String::from("Divide"), vec![a, b]),
+            Self::Subtract(a, b) => (String::from("Subtract"), vec![a, b]),
+            Self::Remainder(a, b) => (String::from("Remainder"), vec![a, b]),
+
+            Self::Greater(a, b) => (String::from("Greater"), vec![a, b]),
+            Self::Less(a, b) => (String::from("Less"), vec![a, b]),
+            Self::GreaterEqual(a, b) => (String::from("GreaterEqual"), vec![a, b]),
+            Self::LessEqual(a, b) => (String::from("LessEqual"), vec![a, b]),
+            Self::Equal(a, b) => (String::from("Equal"), vec![a, b]),
+            Self::NotEqual(a, b) => (String::from("NotEqual"), vec![a, b]),
+
+            Self::Conditional(cond, then, otherwise) => (String::from("Conditional"), vec![cond, then, otherwise]),
+            Self::If(cond, then, otherwise) => (String::from("If"), vec![cond, then, otherwise]),
+            Self::While(cond, body) => (String::from("While"), vec![cond, body]),
+            Self::For(pattern, list, body) => (format!("For {}", pattern), vec![list, body]),
+            Self::Try(body, name, handler) => (format!("Try catch {}", name), vec![body, handler]),
+            Self::Match(value, arms) => {
+                out.push_str(&format!("{}Match\n", "  ".repeat(depth)));
+                value.write_ast(depth + 1, out);
+                for (pattern, guard, body) in arms {
+                    out.push_str(&format!("{}Case {}\n", "  ".repeat(depth + 1), pattern));
+                    if let Some(guard) = guard {
+                        out.push_str(&format!("{}Guard\n", "  ".repeat(depth + 2)));
+                        guard.write_ast(depth + 3, out);
+                    }
+                    body.write_ast(depth + 2, out);
+                }
+                return
+            }
+
+            Self::Path(path) => (format!("Path {}", path.display()), vec![]),
+            Self::Size(size) => (format!("Size {}", size), vec![]),
+            Self::Job(id) => (format!("Job %{}", id), vec![]),
+
+            Self::Boolean(b) => (format!("Boolean {}", b), vec![]),
+            Self::String(s) => (format!("String {:?}", s), vec![]),
+            Self::Integer(n) => (format!("Integer {}", n), vec![]),
+            Self::Float(n) => (format!("Float {}", n), vec![]),
+            Self::List(items) => (String::from("List"), items.iter().collect()),
+            Self::Table(map) => {
+                out.push_str(&format!("{}Table\n", "  ".repeat(depth)));
+                for (name, val) in map {
+                    out.push_str(&format!("{}{}:\n", "  ".repeat(depth + 1), name));
+                    val.write_ast(depth + 2, out);
+                }
+                return
+            }
+            Self::Nil => (String::from("Nil"), vec![]),
+
+            Self::Error(e) => (format!("Error {}", e), vec![]),
+        };
+
+        out.push_str(&format!("{}{}\n", "  ".repeat(depth), label));
+        for child in children {
+            child.write_ast(depth + 1, out);
+        }
+    }
+
     fn get_used_symbols(&self) -> Vec<String> {
         match self {
             Self::Symbol(name) => vec![name.clone()],
@@ -120,10 +375,15 @@ impl Value {
                 result
             }
 
-            Self::Define(_, value) => value.get_used_symbols(),
+            Self::Define(_, value) | Self::Destructure(_, value) | Self::Named(_, value) => value.get_used_symbols(),
 
-            Self::Macro(_, body)     => body.get_used_symbols(),
-            Self::Lambda(_, body, _) => body.get_used_symbols(),
+            Self::Macro(params, body) | Self::Lambda(params, body, _) => {
+                let mut result = body.get_used_symbols();
+                for default in params.iter().filter_map(|param| param.default.as_ref()) {
+                    result.extend(default.get_used_symbols());
+                }
+                result
+            }
 
             Self::While(cond, x) => {
                 let mut result = cond.get_used_symbols();
@@ -131,12 +391,29 @@ impl Value {
                 result
             }
 
+            Self::Try(body, _, handler) => {
+                let mut result = body.get_used_symbols();
+                result.extend(handler.get_used_symbols());
+                result
+            }
+
             Self::For(_, list, x) => {
                 let mut result = list.get_used_symbols();
                 result.extend(x.get_used_symbols());
                 result
             }
 
+            Self::Match(value, arms) => {
+                let mut result = value.get_used_symbols();
+                for (_, guard, body) in arms {
+                    if let Some(guard) = guard {
+                        result.extend(guard.get_used_symbols());
+                    }
+                    result.extend(body.get_used_symbols());
+                }
+                result
+            }
+
             Self::If(a, b, c) | Self::Conditional(a, b, c) => {
                 let mut result = a.get_used_symbols();
                 result.extend(b.get_used_symbols());
@@ -144,7 +421,7 @@ impl Value {
                 result
             }
 
-            Self::List(vals) | Self::Do(vals) | Self::Scope(vals) => {
+            Self::List(vals) | Self::Do(vals) | Self::Scope(vals) | Self::Pipe(vals) | Self::Interpolate(vals) => {
                 let mut result = vec![];
                 for val in vals {
                     result.extend(val.get_used_symbols());
@@ -172,11 +449,25 @@ impl Value {
                 result
             }
 
-            Self::Grouped(x) | Self::Not(x) | Self::Negate(x) => x.get_used_symbols(),
+            Self::Redirect(run, redirections) => {
+                let mut result = run.get_used_symbols();
+                for redirection in redirections {
+                    match redirection {
+                        Redirection::Write(path)
+                        | Redirection::Append(path)
+                        | Redirection::Read(path) => result.extend(path.get_used_symbols()),
+                        Redirection::MergeStderr => {}
+                    }
+                }
+                result
+            }
+
+            Self::Grouped(x) | Self::Not(x) | Self::Negate(x) | Self::Background(x) => x.get_used_symbols(),
 
             Self::Builtin(_, _)
             | Self::Path(_)
             | Self::Size(_)
+            | Self::Job(_)
             | Self::Integer(_)
             | Self::Float(_)
             | Self::String(_)
@@ -193,6 +484,169 @@ impl Value {
         }
     }
 
+    /// Get the command for a `Run` statement with its redirections applied,
+    /// so it can be run as part of a pipeline.
+    fn stage(&self, env: &mut Environment) -> Result<process::Stage, Error> {
+        match self {
+            Self::Run(program, arguments) => match program.eval(env)? {
+                program @ Self::Path(_) | program @ Self::String(_) | program @ Self::Symbol(_) => {
+                    let args = process::eval_args(arguments, env)?;
+                    Ok(process::Stage::new(process::command(&program, &args, env)?))
+                }
+                Self::Error(e) => Err(*e),
+                other => Err(Error::CannotExecuteProgram(other)),
+            },
+
+            Self::Redirect(run, redirections) => {
+                let mut stage = run.stage(env)?;
+                for redirection in redirections {
+                    match redirection {
+                        Redirection::Write(path) => {
+                            let path = path.eval(env)?;
+                            stage.stdout = Some(process::open(&path, OpenOptions::new().write(true).create(true).truncate(true), env)?);
+                        }
+                        Redirection::Append(path) => {
+                            let path = path.eval(env)?;
+                            stage.stdout = Some(process::open(&path, OpenOptions::new().append(true).create(true), env)?);
+                        }
+                        Redirection::Read(path) => {
+                            let path = path.eval(env)?;
+                            stage.stdin = Some(process::open(&path, OpenOptions::new().read(true), env)?);
+                        }
+                        Redirection::MergeStderr => stage.merge_stderr = true,
+                    }
+                }
+                Ok(stage)
+            }
+
+            other => Err(Error::CannotExecuteProgram(other.clone())),
+        }
+    }
+
+    /// Run a command, a command with redirections, a pipeline, or a background job.
+    fn run(&self, env: &mut Environment) -> Result<Value, Error> {
+        match self {
+            Self::Run(program, arguments) => {
+                let program = program.eval(env)?;
+
+                match &program {
+                    Self::Path(_) | Self::String(_) | Self::Symbol(_) => {
+                        let args = process::eval_args(arguments, env)?;
+                        let mut result = process::command(&program, &args, env)?;
+                        let timeout = env.get_timeout()?;
+
+                        match process::status(&mut result, timeout) {
+                            Ok(status) => {
+                                // A program stopped by CTRL+C stops the rest of the evaluation too
+                                process::check_interrupt()?;
+                                match process::exit_code(&status) {
+                                    code if code != 0 && env.get(STRICT)? == Self::Boolean(true) => Err(process::failed(&program, &args, code)),
+                                    code => Ok(Self::Integer(code))
+                                }
+                            }
+                            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(process::timed_out(self.clone(), timeout)),
+                            Err(e) => Err(process::spawn_failed(&result, e))
+                        }
+                    }
+
+                    Self::Error(e) => Ok(Self::Error(e.clone())),
+
+                    other => Self::Apply(Box::new(other.clone()), arguments.clone()).eval(env),
+                }
+            }
+
+            Self::Redirect(_, _) => {
+                let timeout = env.get_timeout()?;
+                let stage = self.stage(env)?;
+                let (program, args) = stage.program();
+                match process::pipeline(vec![stage], timeout)? {
+                    Some(codes) if codes[0] != 0 && env.get(STRICT)? == Self::Boolean(true) => Err(process::failed(&program, &args, codes[0])),
+                    Some(codes) => Ok(Self::Integer(codes[0])),
+                    None => Err(process::timed_out(self.clone(), timeout))
+                }
+            }
+
+            Self::Background(command) => {
+                let stage = command.stage(env)?;
+                Ok(Self::Job(process::background(stage, command.to_string())?))
+            }
+
+            Self::Pipe(stages) => {
+                let mut commands = vec![];
+                let mut programs = vec![];
+                let mut input = None;
+                for (i, stage) in stages.iter().enumerate() {
+                    match stage {
+                        Self::Run(_, _) | Self::Redirect(_, _) => {
+                            let mut command = stage.stage(env)?;
+                            command.input = input.take();
+                            programs.push(command.program());
+                            commands.push(command);
+                        }
+                        // A pipeline can start with a value to write to the first program's input
+                        value if i == 0 => match value.eval(env)? {
+                            Self::Error(e) => return Ok(Self::Error(e)),
+                            value => input = Some(process::input(&value)),
+                        },
+                        other => return Err(Error::CannotExecuteProgram(other.clone())),
+                    }
+                }
+
+                let timeout = env.get_timeout()?;
+                let codes = match process::pipeline(commands, timeout)? {
+                    Some(codes) => codes,
+                    None => return Err(process::timed_out(self.clone(), timeout))
+                };
+                let pipefail = env.get(PIPEFAIL)? == Self::Boolean(true);
+                let last = match codes.iter().rposition(|code| *code != 0) {
+                    Some(i) if pipefail => i,
+                    _ => codes.len() - 1,
+                };
+
+                match codes[last] {
+                    code if code != 0 && env.get(STRICT)? == Self::Boolean(true) => {
+                        let (program, args) = &programs[last];
+                        Err(process::failed(program, args, code))
+                    }
+                    code => Ok(Self::Integer(code))
+                }
+            }
+
+            other => Err(Error::CannotExecuteProgram(other.clone())),
+        }
+    }
+
+    /// Evaluate a parsed program, keeping `LAST-STATUS`, `LAST-DURATION`,
+    /// and `LAST-ERROR` up to date after each of its top level statements.
+    pub fn eval_top_level(&self, env: &mut Environment) -> Result<Value, Error> {
+        let statements = match self {
+            Self::Do(statements) => statements.as_slice(),
+            other => std::slice::from_ref(other)
+        };
+
+        let mut acc = Value::Nil;
+        for statement in statements {
+            let start = Instant::now();
+            // An interrupt that arrived between statements fails the next one, so that it's recorded like any other error
+            let result = process::check_interrupt().and_then(|_| statement.eval(env));
+
+            let (status, error) = match &result {
+                Err(e @ Error::ProgramFailed(_, _, code)) => (*code, Self::Error(Box::new(e.clone()))),
+                Err(e) => (1, Self::Error(Box::new(e.clone()))),
+                Ok(Self::Error(e)) => (1, Self::Error(e.clone())),
+                // Only commands have an exit code, anything else that finishes succeeded
+                Ok(Self::Integer(code)) if matches!(statement, Self::Run(_, _) | Self::Redirect(_, _) | Self::Pipe(_)) => (*code, Self::Nil),
+                Ok(_) => (0, Self::Nil)
+            };
+            env.define(LAST_STATUS, Self::Integer(status));
+            env.define(LAST_DURATION, Self::Float(start.elapsed().as_secs_f64()));
+            env.define(LAST_ERROR, error);
+
+            acc = result?;
+        }
+        Ok(acc)
+    }
+
     pub fn eval(&self, env: &mut Environment) -> Result<Value, Error> {
         match self {
             Self::Index(val, idx) => {
@@ -261,6 +715,7 @@ impl Value {
             Self::While(cond, body) => {
                 let mut acc = Value::Nil;
                 loop {
+                    process::check_interrupt()?;
                     match cond.eval(env)? {
                         Self::Boolean(true) => acc = body.eval(env)?,
                         Self::Boolean(false) => break,
@@ -270,12 +725,15 @@ impl Value {
                 Ok(acc)
             }
 
-            Self::For(name, iter, body) => {
+            Self::For(pattern, iter, body) => {
                 match iter.eval(env)? {
                     Self::List(list) => {
                         let mut acc = Value::Nil;
                         for item in list {
-                            env.define(name, item);
+                            process::check_interrupt()?;
+                            for (name, val) in pattern.destructure(&item)? {
+                                env.define(name, val);
+                            }
                             acc = body.eval(env)?;
                         }
                         Ok(acc)
@@ -285,9 +743,68 @@ impl Value {
                 }
             }
 
+            Self::Match(value, arms) => {
+                // Errors can be matched with `err`, and are raised again if no `err` arm matches them
+                let (value, error) = match value.eval(env) {
+                    Ok(value) => (value, None),
+                    Err(Error::Interrupted) => return Err(Error::Interrupted),
+                    Err(e) => (Self::Error(Box::new(e.clone())), Some(e))
+                };
+
+                for (pattern, guard, body) in arms {
+                    if error.is_some() && !matches!(pattern, Pattern::Type(tag, _) if tag == "err") {
+                        continue
+                    }
+
+                    if let Some(bindings) = pattern.matches(&value) {
+                        // The guard sees the bindings in a scope of its own, so that an arm
+                        // which doesn't match leaves the environment as it was
+                        if let Some(guard) = guard {
+                            let mut scope = env.clone();
+                            for (name, val) in &bindings {
+                                scope.define(name, val.clone());
+                            }
+                            match guard.eval(&mut scope)? {
+                                Self::Boolean(true) => {}
+                                Self::Boolean(false) => continue,
+                                x => return Err(Error::InvalidCondition(x))
+                            }
+                        }
+
+                        for (name, val) in bindings {
+                            env.define(&name, val);
+                        }
+                        return body.eval(env)
+                    }
+                }
+
+                Err(error.unwrap_or(Error::NoMatchingPattern(value)))
+            }
+
+            Self::Try(body, name, handler) => {
+                // Interrupts aren't caught, so that scripts can always be stopped
+                let error = match body.eval(env) {
+                    Err(Error::Interrupted) => return Err(Error::Interrupted),
+                    Ok(Self::Error(e)) => *e,
+                    Err(e) => e,
+                    Ok(value) => return Ok(value)
+                };
+
+                let mut caught = BTreeMap::new();
+                caught.insert(String::from("kind"), Self::string(error.kind()));
+                caught.insert(String::from("message"), Self::string(&error));
+                caught.insert(String::from("data"), match error {
+                    Error::Thrown(value) => value,
+                    _ => Self::Nil
+                });
+                env.define(name, Self::Table(caught));
+                handler.eval(env)
+            }
+
             Self::Do(vals) => {
                 let mut acc = Value::Nil;
                 for val in vals {
+                    process::check_interrupt()?;
                     acc = val.eval(env)?;
                 }
                 Ok(acc)
@@ -312,36 +829,16 @@ impl Value {
 
             Self::Grouped(x) => x.eval(env),
 
-            Self::Run(program, arguments) => {
-                let program = program.eval(env)?;
-
-                match &program {
-                    Self::Path(_) | Self::String(_) | Self::Symbol(_) => {
-                        let mut result = Command::new(&program.to_string());
-                        result.current_dir(env.get_cwd()?);
-
-                        for arg in arguments {
-                            result.arg(&arg.eval(env)?.to_string());
-                        }
+            Self::Named(name, _) => Err(Error::CustomError(format!("named argument `{}` can only be given to a function or macro", name))),
 
-                        if let Ok(status) = result.status() {
-                            if status.success() {
-                                Ok(Self::Integer(0))
-                            } else if let Some(code) = status.code() {
-                                Ok(Self::Integer(code))
-                            } else {
-                                Ok(Self::Integer(1))
-                            }
-                        } else {
-                            Err(Error::CannotExecuteProgram(program.clone()))
-                        }
-                    }
-
-                    Self::Error(e) => Ok(Self::Error(e.clone())),
-
-                    other => Self::Apply(Box::new(other.clone()), arguments.clone()).eval(env),
-                }
-            }
+            Self::Run(_, _) | Self::Redirect(_, _) | Self::Pipe(_) | Self::Background(_) => match self.run(env) {
+                // Let the user decide what happens when a program doesn't exist, however it was run
+                Err(Error::ProgramNotFound(program, args)) => Self::Apply(
+                    Box::new(Self::symbol(COMMAND_NOT_FOUND)),
+                    vec![program, Self::List(args)]
+                ).eval(env),
+                result => result
+            },
 
             Self::Define(name, value) => {
                 let result = value.eval(env)?;
@@ -349,6 +846,14 @@ impl Value {
                 Ok(result)
             }
 
+            Self::Destructure(pattern, value) => {
+                let result = value.eval(env)?;
+                for (name, val) in pattern.destructure(&result)? {
+                    env.define(name, val);
+                }
+                Ok(result)
+            }
+
             Self::GreaterEqual(a, b) => {
                 Ok(match (a.eval(env)?, b.eval(env)?) {
                     (Self::Integer(x), Self::Integer(y)) => Self::Boolean(x>=y),
@@ -408,62 +913,51 @@ impl Value {
             Self::NotEqual(a, b) => Ok(Self::Boolean(a.eval(env)? != b.eval(env)?)),
 
             Self::Apply(func, args) => {
+                process::check_interrupt()?;
                 match func.eval(env)? {
                     Self::Macro(params, body) => {
-                        if params.len() > args.len() {
-                            Err(Error::TooFewArguments(*func.clone(), args.clone()))
-                        } else if params.len() < args.len() {
-                            Err(Error::TooManyArguments(*func.clone(), args.clone()))
-                        } else {
-                            let mut save_vars = BTreeMap::new();
-                            for param in &params {
-                                if env.is_defined(param) {
-                                    save_vars.insert(param.clone(), env.get(param)?);
-                                }
-                            }
+                        let args = match_args(func, &params, args, env)?;
 
-                            for (param, arg) in params.iter().zip(args) {
-                                let val = arg.eval(env)?;
-                                env.define(param.clone(), val);
+                        let mut save_vars = BTreeMap::new();
+                        for param in &params {
+                            if env.is_defined(&param.name) {
+                                save_vars.insert(param.name.clone(), env.get(&param.name)?);
                             }
+                        }
 
-                            let result = body.eval(env);
-
-                            for (key, val) in save_vars {
-                                env.define(key, val);
+                        let mut result = Ok(Self::Nil);
+                        for (param, arg) in params.iter().zip(args) {
+                            match param.value(arg, env) {
+                                Ok(val) => env.define(&param.name, val),
+                                Err(e) => {
+                                    result = Err(e);
+                                    break
+                                }
                             }
+                        }
 
-                            result
-                            // let mut tmp = env.clone();
-                            // for (param, arg) in params.iter().zip(args) {
-                            //     let val = arg.eval(env)?;
-                            //     tmp.define(param.clone(), val);
-                            // }
-                            // let result = body.eval(&mut tmp);
-                            // for (key, val) in tmp.get_symbols() {
-                            //     if !params.contains(key) {
-                            //         env.define(key.clone(), val.clone());
-                            //         println!("DEFINING {} to `{}`", key, val);
-                            //     }
-                            // }
+                        if result.is_ok() {
+                            result = body.eval(env);
                         }
+
+                        for (key, val) in save_vars {
+                            env.define(key, val);
+                        }
+
+                        result
                     }
 
                     Self::Lambda(params, body, captured) => {
-                        if params.len() > args.len() {
-                            Err(Error::TooFewArguments(*func.clone(), args.clone()))
-                        } else if params.len() < args.len() {
-                            Err(Error::TooManyArguments(*func.clone(), args.clone()))
-                        } else {
-                            let mut tmp = env.combine(&captured);
-
-                            for (param, arg) in params.iter().zip(args) {
-                                let val = arg.eval(env)?;
-                                tmp.define(param.clone(), val);
-                            }
+                        let args = match_args(func, &params, args, env)?;
+                        let mut tmp = env.combine(&captured);
 
-                            body.eval(&mut tmp)
+                        // Defaults are evaluated with the parameters before them already defined
+                        for (param, arg) in params.iter().zip(args) {
+                            let val = param.value(arg, &mut tmp)?;
+                            tmp.define(&param.name, val);
                         }
+
+                        body.eval(&mut tmp)
                     }
 
                     Self::Builtin(_, builtin) => builtin(args, env),
@@ -475,7 +969,7 @@ impl Value {
 
             Self::Lambda(params, body, old_env) => {
                 let mut tmp_env = old_env.clone();
-                for symbol in body.get_used_symbols() {
+                for symbol in self.get_used_symbols() {
                     if env.is_defined(&symbol) && !old_env.is_defined(&symbol) {
                         tmp_env.define(symbol.clone(), env.get(&symbol)?);
                     }
@@ -627,6 +1121,14 @@ impl Value {
                 Ok(Self::List(items.iter().map(|x| x.eval(env)).collect::<Result<Vec<Self>, Error>>()?))
             }
 
+            Self::Interpolate(parts) => {
+                let mut result = String::new();
+                for part in parts {
+                    result += &part.eval(env)?.to_string();
+                }
+                Ok(Self::String(result))
+            }
+
             Self::Table(items) => {
                 let mut result = BTreeMap::new();
                 for (key, val) in items {
@@ -639,6 +1141,7 @@ impl Value {
             | Self::Macro(_, _)
             | Self::Path(_)
             | Self::Size(_)
+            | Self::Job(_)
             | Self::Boolean(_)
             | Self::String(_)
             | Self::Integer(_)
@@ -660,6 +1163,7 @@ impl PartialEq for Value {
             (Self::Symbol(x),  Self::Symbol(y))  => x == y,
             (Self::Path(x),    Self::Path(y))    => x == y,
             (Self::Size(x),    Self::Size(y))    => x == y,
+            (Self::Job(x),     Self::Job(y))     => x == y,
             (Self::String(x),  Self::String(y))  => x == y,
             (Self::Boolean(x), Self::Boolean(y)) => x == y,
             (Self::Table(x),   Self::Table(y))   => x == y,
@@ -680,7 +1184,14 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             Self::While(cond, body) => write!(f, "while {} {}", cond, body),
-            Self::For(name, iter, body) => write!(f, "for {} in {} {}", name, iter, body),
+            Self::For(pattern, iter, body) => write!(f, "for {} in {} {}", pattern, iter, body),
+            Self::Match(value, arms) => {
+                let arms = arms.iter().map(|(pattern, guard, body)| match guard {
+                    Some(guard) => format!("{} if {} => {}", pattern, guard, body),
+                    None => format!("{} => {}", pattern, body)
+                }).collect::<Vec<String>>();
+                write!(f, "match {} {{ {} }}", value, arms.join(", "))
+            }
 
             Self::Do(vals) => {
                 let mut result = String::from("{");
@@ -714,8 +1225,23 @@ impl fmt::Display for Value {
             Self::Grouped(x) => write!(f, "({})", x),
 
             Self::Define(name, value) => write!(f, "{} := {}", name, value),
+            Self::Destructure(pattern, value) => write!(f, "{} := {}", pattern, value),
 
             Self::Index(a, b) => write!(f, "{}[{}]", a, b),
+            Self::Named(name, value) => write!(f, "{}: {}", name, value),
+            Self::Interpolate(parts) => {
+                write!(f, "f\"")?;
+                for part in parts {
+                    match part {
+                        Self::String(s) => {
+                            let escaped = format!("{:?}", s);
+                            write!(f, "{}", escaped[1..escaped.len()-1].replace('{', "{{").replace('}', "}}"))?
+                        }
+                        expr => write!(f, "{{{}}}", format!("{:?}", expr).replace('"', "\\\""))?
+                    }
+                }
+                write!(f, "\"")
+            }
             Self::Range(a, b) => write!(f, "{} to {}", a, b),
 
             Self::And(a, b) => write!(f, "{} and {}", a, b),
@@ -737,6 +1263,7 @@ impl fmt::Display for Value {
             Self::NotEqual(a, b) => write!(f, "{} ≠ {}", a, b),
 
             Self::If(cond, a, b) => write!(f, "if ({}) {} else {}", cond, a, b),
+            Self::Try(body, name, handler) => write!(f, "try {} catch {} {}", body, name, handler),
             Self::Conditional(cond, a, b) => write!(f, "{}? {} : {}", cond, a, b),
 
             Self::Lambda(params, body, _) => {
@@ -773,6 +1300,26 @@ impl fmt::Display for Value {
                 Ok(())
             }
 
+            Self::Redirect(run, redirections) => {
+                write!(f, "{}", run)?;
+                for redirection in redirections {
+                    write!(f, " {}", redirection)?;
+                }
+                Ok(())
+            }
+
+            Self::Pipe(stages) => {
+                for (i, stage) in stages.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", stage)?;
+                }
+                Ok(())
+            }
+
+            Self::Background(command) => write!(f, "{} &", command),
+
             Self::Apply(func, args) => {
                 let mut result = format!("{}(", func);
                 for arg in args {
@@ -787,6 +1334,7 @@ impl fmt::Display for Value {
 
             Self::Path(path) => write!(f, "{}", path.as_path().display().to_string()),
             Self::Size(size) => write!(f, "{}", size),
+            Self::Job(id) => write!(f, "%{}", id),
             Self::Integer(x) => write!(f, "{}", x),
             Self::Float(x)   => write!(f, "{}", x),
             Self::String(x)  => write!(f, "{}", x),
@@ -827,7 +1375,14 @@ impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             Self::While(cond, body) => write!(f, "while {:?} {:?}", cond, body),
-            Self::For(name, iter, body) => write!(f, "for {:?} in {:?} {:?}", name, iter, body),
+            Self::For(pattern, iter, body) => write!(f, "for {:?} in {:?} {:?}", pattern, iter, body),
+            Self::Match(value, arms) => {
+                let arms = arms.iter().map(|(pattern, guard, body)| match guard {
+                    Some(guard) => format!("{} if {:?} => {:?}", pattern, guard, body),
+                    None => format!("{} => {:?}", pattern, body)
+                }).collect::<Vec<String>>();
+                write!(f, "match {:?} {{ {} }}", value, arms.join(", "))
+            }
 
             Self::Do(vals) => {
                 let mut result = String::from("{ ");
@@ -861,8 +1416,11 @@ impl fmt::Debug for Value {
             Self::Grouped(x) => write!(f, "({:?})", x),
 
             Self::Define(name, value) => write!(f, "{} := {:?}", name, value),
+            Self::Destructure(pattern, value) => write!(f, "{} := {:?}", pattern, value),
 
             Self::Index(a, b) => write!(f, "{:?}[{:?}]", a, b),
+            Self::Named(name, value) => write!(f, "{}: {:?}", name, value),
+            Self::Interpolate(_) => write!(f, "{}", self),
 
             Self::Range(a, b) => write!(f, "{:?} to {:?}", a, b),
 
@@ -885,6 +1443,7 @@ impl fmt::Debug for Value {
             Self::NotEqual(a, b) => write!(f, "{:?} ≠ {:?}", a, b),
 
             Self::If(cond, a, b) => write!(f, "if ({:?}) {:?} else {:?}", cond, a, b),
+            Self::Try(body, name, handler) => write!(f, "try {:?} catch {} {:?}", body, name, handler),
             Self::Conditional(cond, a, b) => write!(f, "{:?}? {:?} : {:?}", cond, a, b),
 
             Self::Lambda(params, body, _) => {
@@ -921,6 +1480,26 @@ impl fmt::Debug for Value {
                 Ok(())
             }
 
+            Self::Redirect(run, redirections) => {
+                write!(f, "{:?}", run)?;
+                for redirection in redirections {
+                    write!(f, " {:?}", redirection)?;
+                }
+                Ok(())
+            }
+
+            Self::Pipe(stages) => {
+                for (i, stage) in stages.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{:?}", stage)?;
+                }
+                Ok(())
+            }
+
+            Self::Background(command) => write!(f, "{:?} &", command),
+
             Self::Apply(func, args) => {
                 let mut result = format!("{:?}(", func);
                 for arg in args {
@@ -935,6 +1514,7 @@ impl fmt::Debug for Value {
 
             Self::Path(path) => write!(f, "{}", path.as_path().display().to_string()),
             Self::Size(size) => write!(f, "{}", size),
+            Self::Job(id) => write!(f, "%{}", id),
             Self::Integer(x) => write!(f, "{:?}", x),
             Self::Float(x)   => write!(f, "{:?}", x),
             Self::String(x)  => write!(f, "{:?}", x),
@@ -968,4 +1548,95 @@ impl fmt::Debug for Value {
             Self::Error(e) => write!(f, "{}", e)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Environment, Value};
+    use crate::parse;
+
+    fn eval(code: &str) -> Value {
+        let mut env = Environment::new();
+        parse(code).and_then(|program| program.eval(&mut env)).unwrap()
+    }
+
+    fn error(code: &str) -> &'static str {
+        let mut env = Environment::new();
+        parse(code).and_then(|program| program.eval(&mut env)).unwrap_err().kind()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn command_not_found_hook_handles_every_kind_of_command() {
+        let hook = r#"command-not-found := \program, args -> [program, args];"#;
+        let expected = Value::List(vec![Value::string("atom-missing-program"), Value::List(vec![Value::string("x")])]);
+        assert_eq!(eval(&format!("{} atom-missing-program' x", hook)), expected);
+        assert_eq!(eval(&format!("{} printf' hi | atom-missing-program' x", hook)), expected);
+        assert_eq!(eval(&format!("{} atom-missing-program' x > \"/dev/null\"", hook)), expected);
+        assert_eq!(eval(&format!("{} atom-missing-program' x &", hook)), expected);
+    }
+
+    #[test]
+    fn match_guards_only_bind_names_for_the_arm_they_pick() {
+        assert_eq!(eval(r#"x := 5; match 3 { x if x > 10 => "big", _ => "other" }; x"#), Value::Integer(5));
+        assert_eq!(eval(r#"x := 5; match 30 { x if x > 10 => x + 1, _ => 0 }"#), Value::Integer(31));
+    }
+
+    #[test]
+    fn arguments_fill_defaults_rest_and_named_parameters() {
+        let f = r#"f := fn(a, b = a * 2, ...rest) -> [a, b, rest];"#;
+        let list = Value::List;
+        assert_eq!(eval(&format!("{} f(1)", f)), list(vec![Value::Integer(1), Value::Integer(2), list(vec![])]));
+        assert_eq!(eval(&format!("{} f(1, 5, 6, 7)", f)), list(vec![Value::Integer(1), Value::Integer(5), list(vec![Value::Integer(6), Value::Integer(7)])]));
+        assert_eq!(eval(&format!("{} f(b: 3, a: 4)", f)), list(vec![Value::Integer(4), Value::Integer(3), list(vec![])]));
+        assert_eq!(eval(r#"m := macro(x, y = "default") -> y; m(1)"#), Value::String(String::from("default")));
+        assert_eq!(eval(r#"make := fn(x) -> fn(y = x) -> y; make(5)()"#), Value::Integer(5));
+    }
+
+    #[test]
+    fn arguments_that_dont_fit_are_errors() {
+        let f = r#"f := fn(a, b = 0) -> a;"#;
+        assert_eq!(error(&format!("{} f()", f)), "TooFewArguments");
+        assert_eq!(error(&format!("{} f(1, 2, 3)", f)), "TooManyArguments");
+        assert_eq!(error(&format!("{} f(1, c: 2)", f)), "UnknownParameter");
+        assert_eq!(error(&format!("{} f(1, a: 2)", f)), "DuplicateArgument");
+    }
+
+    #[test]
+    fn destructuring_assigns_names_that_are_also_types() {
+        assert_eq!(eval(r#"path := "old"; [path, n] := [to-path("/tmp"), 1]; path"#), Value::Path(std::path::PathBuf::from("/tmp")));
+        assert_eq!(eval(r#"total := 0; for [size, name] in [[1, "a"], [2, "b"]] { total := total + size }; total"#), Value::Integer(3));
+    }
+
+    #[test]
+    fn raised_errors_only_match_err_arms() {
+        let f = "f := fn(a) -> a;";
+        assert_eq!(eval(&format!(r#"{} match f() {{ e: err => "caught", _ => "ok" }}"#, f)), Value::String(String::from("caught")));
+        assert_eq!(error(&format!(r#"{} match f() {{ _ => "ok" }}"#, f)), "TooFewArguments");
+        assert_eq!(error(&format!(r#"{} match f() {{ x => x }}"#, f)), "TooFewArguments");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn redirections_apply_in_any_order() {
+        let path = std::env::temp_dir().join(format!("atom-redirect-order-{}", std::process::id()));
+        let program = r#""sh"' -c "echo out; echo err >&2""#;
+        for redirections in [format!("2>&1 > {:?}", path.display().to_string()), format!("> {:?} 2>&1", path.display().to_string())] {
+            eval(&format!("{} {}", program, redirections));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "out\nerr\n");
+        }
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn lists_are_split_into_arguments_for_programs() {
+        let path = std::env::temp_dir().join(format!("atom-list-args-{}", std::process::id()));
+        // Strings in a list aren't patterns, so they're passed as they are even with `GLOB` set
+        for glob in ["false", "true"] {
+            eval(&format!(r#"GLOB := {}; args := ["a b", "*", 1]; "printf"' "<%s>" args > {:?}"#, glob, path.display().to_string()));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "<a b><*><1>");
+        }
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    process::{Command, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Run atom with the given arguments, and wait for it to finish.
+fn atom(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_atom"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .expect("could not run atom")
+}
+
+/// Run atom with the given arguments, and interrupt it like CTRL+C would after a delay.
+#[cfg(unix)]
+fn interrupted_atom(args: &[&str], delay: Duration) -> (Output, Duration) {
+    let start = Instant::now();
+    let child = Command::new(env!("CARGO_BIN_EXE_atom"))
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("could not run atom");
+    thread::sleep(delay);
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT); }
+    let output = child.wait_with_output().expect("could not wait for atom");
+    (output, start.elapsed())
+}
+
+#[test]
+fn runs_code_from_the_command_line() {
+    let output = atom(&["-c", "print(1 + 2)"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+}
+
+#[test]
+fn exits_with_the_status_of_the_last_statement() {
+    assert_eq!(atom(&["-c", "x := 1"]).status.code(), Some(0));
+    assert_eq!(atom(&["-c", r#""sh"' -c "exit 3""#]).status.code(), Some(3));
+}
+
+#[test]
+fn fails_when_a_statement_fails() {
+    let output = atom(&["-c", r#"x := 1 + "a"; print("after")"#]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("after"));
+
+    let output = atom(&["-c", r#"STRICT := true; "sh"' -c "exit 3"; print("after")"#]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+#[cfg(unix)]
+fn runs_exit_hooks_after_an_interrupt() {
+    let (output, _) = interrupted_atom(&["-c", r#"on-exit(\ -> print("bye")); "sleep"' 5"#], Duration::from_millis(500));
+    assert_eq!(output.status.code(), Some(130));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "bye\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn interrupts_a_job_in_the_foreground() {
+    let (output, elapsed) = interrupted_atom(
+        &["-c", r#"j := proc@spawn("sleep", ["5"]); fg(j); print("after")"#],
+        Duration::from_millis(500)
+    );
+    assert!(elapsed < Duration::from_secs(4), "fg kept waiting for {:?}", elapsed);
+    assert_eq!(output.status.code(), Some(130));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("after"));
+}
+
+#[test]
+#[cfg(unix)]
+fn interrupts_waiting_on_a_job() {
+    let (output, elapsed) = interrupted_atom(
+        // The job keeps running, so it mustn't hold on to atom's output
+        &["-c", r#"j := proc@spawn("sh", ["-c", "exec sleep 5 > /dev/null 2>&1"]); wait(j); print("after")"#],
+        Duration::from_millis(500)
+    );
+    assert!(elapsed < Duration::from_secs(4), "wait kept waiting for {:?}", elapsed);
+    assert_eq!(output.status.code(), Some(130));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("after"));
+}
+
+#[test]
+#[cfg(unix)]
+fn interrupts_a_program_without_a_terminal() {
+    // Without a terminal, the program only receives CTRL+C from atom
+    let (output, elapsed) = interrupted_atom(&["-c", r#""sleep"' 5; print("after")"#], Duration::from_millis(500));
+    assert!(elapsed < Duration::from_secs(4), "the program kept running for {:?}", elapsed);
+    assert_eq!(output.status.code(), Some(130));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("after"));
+}
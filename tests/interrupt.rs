@@ -0,0 +1,17 @@
+//! Interrupts are global to the process, so these tests are kept apart from the others.
+use atomsh::{clear_interrupt, interrupt, parse, Environment, Error, Value, LAST_ERROR, LAST_STATUS};
+
+#[test]
+fn interrupts_between_statements_are_recorded() {
+    let mut env = Environment::new();
+    env.define(LAST_STATUS, Value::Integer(0));
+
+    interrupt();
+    let result = parse("x := 1; x := 2").unwrap().eval_top_level(&mut env);
+    clear_interrupt();
+
+    assert_eq!(result, Err(Error::Interrupted));
+    assert_eq!(env.get(LAST_STATUS).unwrap(), Value::Integer(1));
+    assert_eq!(env.get(LAST_ERROR).unwrap(), Value::Error(Box::new(Error::Interrupted)));
+    assert!(env.get("x").is_err());
+}